@@ -0,0 +1,372 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! User defined alert rules that are checked against the query table after each update
+
+use crate::{utils::get_client, webhook::Webhook};
+use postgres_types::ToSql;
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::json;
+use std::{error::Error as StdError, net::IpAddr, time::Instant};
+use tokio_postgres::{Error, Row};
+
+/// Most auctions sent for one rule in a single update
+const MAX_MATCHES_PER_RUN: i64 = 25;
+/// Hosts a DISCORD webhook URL can be on
+const DISCORD_HOSTS: [&str; 4] = [
+    "discord.com",
+    "discordapp.com",
+    "ptb.discord.com",
+    "canary.discord.com",
+];
+
+#[derive(Serialize)]
+pub struct AlertRule {
+    pub id: i32,
+    pub name: String,
+    pub webhook_url: String,
+    /// DISCORD sends an embed, HTTP posts the matched auctions as JSON
+    pub webhook_type: String,
+    pub item_id: Option<String>,
+    pub internal_id: Option<String>,
+    pub tier: Option<String>,
+    pub bin: Option<bool>,
+    /// Maximum starting bid or highest bid
+    pub max_price: Option<i64>,
+    pub min_stars: Option<i16>,
+    pub recombobulated: Option<bool>,
+    /// Every enchant must be on the item (formatted as ENCHANT;LEVEL)
+    pub enchants: Vec<String>,
+    pub created_t: i64,
+}
+
+impl From<Row> for AlertRule {
+    fn from(row: Row) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            webhook_url: row.get("webhook_url"),
+            webhook_type: row.get("webhook_type"),
+            item_id: row.get("item_id"),
+            internal_id: row.get("internal_id"),
+            tier: row.get("tier"),
+            bin: row.get("bin"),
+            max_price: row.get("max_price"),
+            min_stars: row.get("min_stars"),
+            recombobulated: row.get("recombobulated"),
+            enchants: row.get("enchants"),
+            created_t: row.get("created_t"),
+        }
+    }
+}
+
+impl AlertRule {
+    /// If the rule has at least one condition, so it doesn't match every auction
+    pub fn has_conditions(&self) -> bool {
+        self.item_id.is_some()
+            || self.internal_id.is_some()
+            || self.tier.is_some()
+            || self.bin.is_some()
+            || self.max_price.is_some()
+            || self.min_stars.is_some()
+            || self.recombobulated.is_some()
+            || !self.enchants.is_empty()
+    }
+
+    /// SQL conditions on the query table that an auction has to match. Their parameters are added
+    /// to `param_vec`
+    pub fn get_conditions<'a>(
+        &'a self,
+        param_vec: &mut Vec<&'a (dyn ToSql + Sync)>,
+    ) -> Vec<String> {
+        let mut conditions = Vec::new();
+
+        if let Some(item_id) = &self.item_id {
+            param_vec.push(item_id);
+            conditions.push(format!("item_id = ${}", param_vec.len()));
+        }
+        if let Some(internal_id) = &self.internal_id {
+            param_vec.push(internal_id);
+            conditions.push(format!("internal_id = ${}", param_vec.len()));
+        }
+        if let Some(tier) = &self.tier {
+            param_vec.push(tier);
+            conditions.push(format!("tier = ${}", param_vec.len()));
+        }
+        if let Some(bin) = &self.bin {
+            param_vec.push(bin);
+            conditions.push(format!("bin = ${}", param_vec.len()));
+        }
+        if let Some(max_price) = &self.max_price {
+            param_vec.push(max_price);
+            conditions.push(format!(
+                "GREATEST(starting_bid, highest_bid) <= ${}",
+                param_vec.len()
+            ));
+        }
+        if let Some(min_stars) = &self.min_stars {
+            param_vec.push(min_stars);
+            conditions.push(format!("stars >= ${}", param_vec.len()));
+        }
+        if let Some(recombobulated) = &self.recombobulated {
+            param_vec.push(recombobulated);
+            conditions.push(format!("recombobulated = ${}", param_vec.len()));
+        }
+        if !self.enchants.is_empty() {
+            param_vec.push(&self.enchants);
+            conditions.push(format!("enchants @> ${}", param_vec.len()));
+        }
+
+        conditions
+    }
+}
+
+#[derive(Serialize)]
+pub struct AlertAuction {
+    pub uuid: String,
+    pub item_name: String,
+    pub internal_id: String,
+    pub tier: String,
+    pub bin: bool,
+    pub starting_bid: i64,
+    pub highest_bid: i64,
+    pub end_t: i64,
+}
+
+impl From<Row> for AlertAuction {
+    fn from(row: Row) -> Self {
+        Self {
+            uuid: row.get("uuid"),
+            item_name: row.get("item_name"),
+            internal_id: row.get("internal_id"),
+            tier: row.get("tier"),
+            bin: row.get("bin"),
+            starting_bid: row.get("starting_bid"),
+            highest_bid: row.get("highest_bid"),
+            end_t: row.get("end_t"),
+        }
+    }
+}
+
+/* Checks every alert rule and sends auctions that weren't already sent for that rule */
+pub async fn evaluate_alerts(time_t: i64) -> (String, String) {
+    let alerts_started = Instant::now();
+    match evaluate_alerts_database(time_t).await {
+        Ok((sent, rules)) => (
+            if sent > 0 {
+                format!(
+                    "\nSending {} alerts for {} rules (found in {}ms)",
+                    sent,
+                    rules,
+                    alerts_started.elapsed().as_millis()
+                )
+            } else {
+                String::new()
+            },
+            String::new(),
+        ),
+        Err(e) => (String::new(), format!("\nError evaluating alerts: {}", e)),
+    }
+}
+
+async fn evaluate_alerts_database(time_t: i64) -> Result<(usize, usize), Error> {
+    let database = get_client().await;
+
+    // Auctions last at most two weeks, so older sent alerts can't be sent again
+    database
+        .execute(
+            "DELETE FROM alert_sent WHERE time_t < $1",
+            &[&(time_t - 1209600000)],
+        )
+        .await?;
+
+    let rules = database
+        .query("SELECT * FROM alerts", &[])
+        .await?
+        .into_iter()
+        .map(AlertRule::from)
+        .collect::<Vec<AlertRule>>();
+
+    let mut sent = 0;
+    let mut rules_sent = 0;
+    for rule in rules {
+        let mut param_vec: Vec<&(dyn ToSql + Sync)> = vec![&rule.id, &time_t];
+        let conditions = rule.get_conditions(&mut param_vec);
+
+        if conditions.is_empty() {
+            continue;
+        }
+
+        // Remembers the matches so each auction is only sent once per rule
+        let auctions = database
+            .query(
+                &format!(
+                    "WITH matches AS (
+                        SELECT uuid, item_name, internal_id, tier, bin, starting_bid, highest_bid, end_t FROM query
+                        WHERE {} AND NOT EXISTS (SELECT 1 FROM alert_sent WHERE alert_id = $1 AND alert_sent.uuid = query.uuid)
+                        ORDER BY GREATEST(starting_bid, highest_bid) LIMIT {MAX_MATCHES_PER_RUN}
+                    ), sent AS (
+                        INSERT INTO alert_sent (alert_id, uuid, time_t) SELECT $1, uuid, $2 FROM matches
+                        ON CONFLICT DO NOTHING RETURNING uuid
+                    )
+                    SELECT matches.* FROM matches JOIN sent USING (uuid) ORDER BY GREATEST(starting_bid, highest_bid)",
+                    conditions.join(" AND ")
+                ),
+                &param_vec,
+            )
+            .await?
+            .into_iter()
+            .map(AlertAuction::from)
+            .collect::<Vec<AlertAuction>>();
+
+        if auctions.is_empty() {
+            continue;
+        }
+
+        sent += auctions.len();
+        rules_sent += 1;
+        // Sent in the background so a slow webhook doesn't delay the next update
+        tokio::spawn(async move {
+            if let Err(e) = send_alert(&rule, &auctions).await {
+                log::error!("Error sending alert {} to webhook: {}", rule.id, e);
+
+                // Forget the auctions so they are sent again after the next update
+                let uuids = auctions
+                    .into_iter()
+                    .map(|auction| auction.uuid)
+                    .collect::<Vec<String>>();
+                if let Err(e) = get_client()
+                    .await
+                    .execute(
+                        "DELETE FROM alert_sent WHERE alert_id = $1 AND uuid = ANY($2)",
+                        &[&rule.id, &uuids],
+                    )
+                    .await
+                {
+                    log::error!("Error removing unsent alerts of {}: {}", rule.id, e);
+                }
+            }
+        });
+    }
+
+    Ok((sent, rules_sent))
+}
+
+/// Sends the matched auctions to the webhook of the rule. Its URL was checked with
+/// `check_webhook_url` when it was created, unless it was created by an admin
+async fn send_alert(
+    rule: &AlertRule,
+    auctions: &[AlertAuction],
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let webhook = Webhook::from_url(&rule.webhook_url);
+    if rule.webhook_type == "HTTP" {
+        webhook
+            .send_json(&json!({
+                "alert": {"id": rule.id, "name": rule.name},
+                "auctions": auctions
+            }))
+            .await
+    } else {
+        let description = auctions
+            .iter()
+            .map(|auction| {
+                format!(
+                    "**{}** {} for {} coins `/viewauction {}`",
+                    auction.item_name,
+                    if auction.bin { "BIN" } else { "auction" },
+                    auction.starting_bid.max(auction.highest_bid),
+                    auction.uuid
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        webhook
+            .send(|message| {
+                message.embed(|embed| {
+                    embed
+                        .title(&format!("Alert: {}", rule.name))
+                        .color(0x00FF00)
+                        .description(&description)
+                })
+            })
+            .await
+    }
+}
+
+/// Checks the webhook URL of a rule created by a key without the admin scope, so alerts can't be
+/// sent into the network the API runs in. DISCORD webhooks must be on a Discord host, and HTTP
+/// webhooks must not be on a private or loopback address. Both must be https
+pub fn check_webhook_url(webhook_type: &str, webhook_url: &str) -> Result<(), String> {
+    let url = Url::parse(webhook_url)
+        .map_err(|e| format!("Error parsing webhook_url parameter: {}", e))?;
+    if url.scheme() != "https" {
+        return Err(String::from(
+            "The webhook_url parameter must be an https URL",
+        ));
+    }
+
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if webhook_type == "DISCORD" {
+        if !DISCORD_HOSTS.contains(&host) || !url.path().starts_with("/api/webhooks/") {
+            return Err(String::from(
+                "The webhook_url parameter must be a Discord webhook URL",
+            ));
+        }
+    } else if host == "localhost"
+        || host.ends_with(".localhost")
+        || host.parse::<IpAddr>().is_ok_and(is_private_ip)
+    {
+        return Err(String::from(
+            "The webhook_url parameter cannot be a private or loopback address",
+        ));
+    }
+
+    Ok(())
+}
+
+/// If the address is not reachable from the internet (private, loopback, link local, shared, or unspecified)
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Shared address space (100.64.0.0/10)
+                || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_ip(IpAddr::V4(ip)),
+            None => {
+                let first_segment = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local (fc00::/7) and link local (fe80::/10)
+                    || first_segment & 0xfe00 == 0xfc00
+                    || first_segment & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
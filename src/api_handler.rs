@@ -17,17 +17,23 @@
  */
 
 use crate::{
+    alerts::evaluate_alerts,
     config::{Config, Feature},
+    error::SkippedAuctions,
+    item_key::ItemKey,
+    metrics::{observe_fetch, observe_parse},
+    snapshot::*,
     statics::*,
+    stream::{has_subscribers, publish, StreamEvent},
     structs::*,
+    underbin::{UnderbinCandidate, UnderbinRules, UnderbinScore},
     utils::*,
 };
 use dashmap::{DashMap, DashSet};
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
-use log::{debug, info};
+use log::{debug, info, warn};
 use serde_json::{json, Value};
 use std::{
-    fs,
     sync::{Arc, Mutex},
     thread,
     time::{Duration, Instant},
@@ -42,12 +48,15 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
     let mut started_epoch = get_timestamp_millis() as i64;
     let previous_started_epoch = *LAST_UPDATED.lock().await;
     // Periodically fetch entire ah to correct excess/missing auctions (please fix your API Hypixel)
-    let last_updated = if *TOTAL_UPDATES.lock().await % 5 == 0 {
+    let last_updated = if let Some(replay_last_updated) = get_replay_last_updated(&config).await {
+        replay_last_updated
+    } else if *TOTAL_UPDATES.lock().await % 5 == 0 {
         0
     } else {
         previous_started_epoch
     };
     let is_full_update = last_updated == 0;
+    begin_snapshot(&config, started_epoch, last_updated).await;
 
     // Stores all auction uuids in auctions vector to prevent duplicates
     let inserted_uuids: DashSet<String> = DashSet::new();
@@ -57,11 +66,21 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
     let under_bin_prices: DashMap<String, Value> = DashMap::new();
     let avg_ah_prices: DashMap<String, AvgSum> = DashMap::new();
     let avg_bin_prices: DashMap<String, AvgSum> = DashMap::new();
-    let past_bin_prices: DashMap<String, f32> = serde_json::from_str(
-        &fs::read_to_string("lowestbin.json").unwrap_or_else(|_| String::from("{}")),
-    )
-    .unwrap();
+    let pet_avg_bin_prices: DashMap<String, AvgSum> = DashMap::new();
+    let past_bin_prices: DashMap<String, f32> = if config.is_enabled(Feature::Underbin) {
+        get_past_bin_prices().await
+    } else {
+        DashMap::new()
+    };
+    // Recent sales keep a mislisted previous lowest bin from looking like profit
+    let recent_bin_averages: DashMap<String, AverageDatabaseItem> =
+        if config.is_enabled(Feature::Underbin) && config.is_enabled(Feature::AverageBin) {
+            get_recent_bin_averages(config.underbin_rules.average_hours).await
+        } else {
+            DashMap::new()
+        };
     let ended_auction_uuids: DashSet<String> = DashSet::new();
+    let skipped_auctions = SkippedAuctions::default();
 
     // Get which APIs to update
     let update_query = config.is_enabled(Feature::Query);
@@ -77,11 +96,12 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
     // Only fetch auctions if any of APIs that need the auctions are enabled
     if update_query || update_lowestbin || update_underbin {
         // First page to get the total number of pages
-        let json_opt = get_auction_page(0).await;
+        let json_opt = get_auction_page(&config, 0).await;
         if json_opt.is_none() {
             error(String::from(
                 "Failed to fetch the first auction page. Canceling this run.",
             ));
+            discard_snapshot(&config).await;
             return true;
         }
 
@@ -90,23 +110,30 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
 
         // May run too early sometimes
         if started_epoch == previous_started_epoch {
+            discard_snapshot(&config).await;
             thread::sleep(Duration::from_secs(1));
             return false;
         }
 
         // Parse the first page's auctions and append them to the prices
+        let before_page_parse = Instant::now();
         let finished = parse_auctions(
+            &config.underbin_rules,
             json.auctions,
             &inserted_uuids,
             &query_prices,
             &bin_prices,
             &under_bin_prices,
             &past_bin_prices,
+            &recent_bin_averages,
+            &skipped_auctions,
             update_query,
             update_lowestbin,
             update_underbin,
             last_updated,
+            previous_started_epoch,
         );
+        observe_parse("auctions", before_page_parse);
 
         if is_full_update {
             debug!("Sending {} async requests", json.total_pages);
@@ -114,16 +141,20 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
             for page_number in 1..json.total_pages {
                 futures.push(
                     process_auction_page(
+                        &config,
                         page_number,
                         &inserted_uuids,
                         &query_prices,
                         &bin_prices,
                         &under_bin_prices,
                         &past_bin_prices,
+                        &recent_bin_averages,
+                        &skipped_auctions,
                         update_query,
                         update_lowestbin,
                         update_underbin,
                         last_updated,
+                        previous_started_epoch,
                     )
                     .boxed(),
                 );
@@ -131,16 +162,20 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
         } else if !finished {
             for page_number in 1..json.total_pages {
                 if process_auction_page(
+                    &config,
                     page_number,
                     &inserted_uuids,
                     &query_prices,
                     &bin_prices,
                     &under_bin_prices,
                     &past_bin_prices,
+                    &recent_bin_averages,
+                    &skipped_auctions,
                     update_query,
                     update_lowestbin,
                     update_underbin,
                     last_updated,
+                    previous_started_epoch,
                 )
                 .await
                 {
@@ -154,9 +189,12 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
     if update_average_auction || update_average_bin || update_pets || !is_full_update {
         futures.push(
             parse_ended_auctions(
+                &config,
                 &avg_ah_prices,
                 &avg_bin_prices,
+                &pet_avg_bin_prices,
                 &pet_prices,
+                &skipped_auctions,
                 update_average_auction,
                 update_average_bin,
                 update_pets,
@@ -172,6 +210,10 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
 
     let fetch_sec = started.elapsed().as_secs_f32();
     info!("Total fetch time: {:.2}s", fetch_sec);
+    METRICS
+        .update_seconds
+        .with_label_values(&["fetch"])
+        .observe(fetch_sec as f64);
 
     debug!("Inserting into database");
     let insert_started = Instant::now();
@@ -192,6 +234,7 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
                 last_updated,
                 update_underbin,
                 &under_bin_prices,
+                started_epoch,
             )
             .boxed(),
         );
@@ -219,7 +262,27 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
         );
     }
 
-    let logs: Vec<(String, String)> = insert_futures.collect().await;
+    if update_average_bin && !pet_avg_bin_prices.is_empty() {
+        insert_futures.push(
+            update_average_fn(
+                "pet level average bins",
+                "pet_average_bin",
+                pet_avg_bin_prices,
+                started_epoch,
+            )
+            .boxed(),
+        );
+    }
+
+    let mut logs: Vec<(String, String)> = insert_futures.collect().await;
+    // Alerts are checked against the query table, so it must be updated first
+    if update_query && config.is_enabled(Feature::Alerts) {
+        logs.push(evaluate_alerts(started_epoch).await);
+    }
+    if let Some(skipped_summary) = skipped_auctions.summary() {
+        ok_logs.push('\n');
+        ok_logs.push_str(&skipped_summary);
+    }
     for ele in logs {
         if !ele.0.is_empty() {
             ok_logs.push_str(&ele.0);
@@ -246,6 +309,14 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
         insert_started.elapsed().as_secs_f32(),
         started.elapsed().as_secs_f32()
     ));
+    METRICS
+        .update_seconds
+        .with_label_values(&["insert"])
+        .observe(insert_started.elapsed().as_secs_f64());
+    METRICS
+        .update_seconds
+        .with_label_values(&["total"])
+        .observe(started.elapsed().as_secs_f64());
 
     *TOTAL_UPDATES.lock().await += 1;
     *LAST_UPDATED.lock().await = started_epoch;
@@ -255,20 +326,24 @@ pub async fn update_auctions(config: Arc<Config>) -> bool {
 }
 
 async fn process_auction_page(
+    config: &Config,
     page_number: i32,
     inserted_uuids: &DashSet<String>,
     query_prices: &Mutex<Vec<QueryDatabaseItem>>,
     bin_prices: &DashMap<String, f32>,
     under_bin_prices: &DashMap<String, Value>,
     past_bin_prices: &DashMap<String, f32>,
+    recent_bin_averages: &DashMap<String, AverageDatabaseItem>,
+    skipped_auctions: &SkippedAuctions,
     update_query: bool,
     update_lowestbin: bool,
     update_underbin: bool,
     last_updated: i64,
+    stream_since: i64,
 ) -> bool {
     let before_page_request = Instant::now();
     // Get the page from the Hypixel API
-    if let Some(page_request) = get_auction_page(page_number).await {
+    if let Some(page_request) = get_auction_page(config, page_number).await {
         debug!("---------------- Fetching page {}", page_request.page);
         debug!(
             "Request time: {}ms",
@@ -278,17 +353,22 @@ async fn process_auction_page(
         // Parse the auctions and append them to the prices
         let before_page_parse = Instant::now();
         let is_finished = parse_auctions(
+            &config.underbin_rules,
             page_request.auctions,
             inserted_uuids,
             query_prices,
             bin_prices,
             under_bin_prices,
             past_bin_prices,
+            recent_bin_averages,
+            skipped_auctions,
             update_query,
             update_lowestbin,
             update_underbin,
             last_updated,
+            stream_since,
         );
+        observe_parse("auctions", before_page_parse);
         debug!(
             "Parsing time: {}ms",
             before_page_parse.elapsed().as_millis()
@@ -307,16 +387,20 @@ async fn process_auction_page(
 
 /* Parses a page of auctions and updates query, lowestbin, and underbin */
 fn parse_auctions(
+    underbin_rules: &UnderbinRules,
     auctions: Vec<Auction>,
     inserted_uuids: &DashSet<String>,
     query_prices: &Mutex<Vec<QueryDatabaseItem>>,
     bin_prices: &DashMap<String, f32>,
     under_bin_prices: &DashMap<String, Value>,
     past_bin_prices: &DashMap<String, f32>,
+    recent_bin_averages: &DashMap<String, AverageDatabaseItem>,
+    skipped_auctions: &SkippedAuctions,
     update_query: bool,
     update_lowestbin: bool,
     update_underbin: bool,
     last_updated: i64,
+    stream_since: i64,
 ) -> bool {
     let is_full_update = last_updated == 0;
 
@@ -329,11 +413,37 @@ fn parse_auctions(
         if inserted_uuids.insert(auction.uuid.to_string()) {
             let mut tier = auction.tier;
 
-            let nbt = &parse_nbt(&auction.item_bytes).unwrap().i[0];
+            let nbt = match parse_nbt(&auction.item_bytes) {
+                Ok(nbt) => nbt,
+                Err(e) => {
+                    skipped_auctions.record(&auction.uuid, &e);
+                    continue;
+                }
+            };
             let extra_attrs = &nbt.tag.extra_attributes;
             let id = extra_attrs.id.to_owned();
-            let mut lowestbin_id = id.to_owned();
-            let mut lowestbin_price = auction.starting_bid as f32 / nbt.count as f32;
+            // Only bins are tracked under their item key, other auctions keep their id
+            let item_key = if auction.bin && update_lowestbin {
+                match ItemKey::from_item(extra_attrs, &nbt.tag.display.name) {
+                    Ok(item_key) => Some(item_key),
+                    Err(e) => {
+                        // Still added to the query table, just not to the lowest and under bins
+                        warn!("Not tracking lowest bin of auction {}: {}", auction.uuid, e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            let pet_level_id = item_key
+                .as_ref()
+                .and_then(|item_key| item_key.get_pet_level_id(extra_attrs, &nbt.tag.display.name));
+            let lowestbin_price = auction.starting_bid as f32
+                / nbt.count as f32
+                / item_key.as_ref().map_or(1, |item_key| item_key.units) as f32;
+            let has_item_key = item_key.is_some();
+            let lowestbin_id =
+                item_key.map_or_else(|| id.to_owned(), |item_key| item_key.internal_id);
 
             let mut enchants = Vec::new();
             let mut attributes = Vec::new();
@@ -356,127 +466,107 @@ fn parse_auctions(
 
             if id == "PET" {
                 // If the pet is tier boosted, the tier field in the auction shows the rarity after boosting
-                tier = serde_json::from_str::<PetInfo>(extra_attrs.pet.as_ref().unwrap())
-                    .unwrap()
-                    .tier;
-
-                if auction.bin && update_lowestbin {
-                    let mut split = auction.item_name.split("] ");
-                    split.next();
-
-                    if let Some(pet_name) = split.next() {
-                        lowestbin_id = format!(
-                            "{};{}",
-                            pet_name.replace(' ', "_").replace("_✦", "").to_uppercase(),
-                            match tier.as_str() {
-                                "COMMON" => 0,
-                                "UNCOMMON" => 1,
-                                "RARE" => 2,
-                                "EPIC" => 3,
-                                "LEGENDARY" => 4,
-                                "MYTHIC" => 5,
-                                _ => -1,
-                            }
-                        );
+                tier = match extra_attrs.get_pet_info() {
+                    Ok(pet_info) => pet_info.tier,
+                    Err(e) => {
+                        skipped_auctions.record(&auction.uuid, &e);
+                        continue;
                     }
-                }
+                };
             }
 
-            if auction.bin && update_lowestbin {
-                if let Some(attributes) = &extra_attrs.attributes {
-                    if id == "ATTRIBUTE_SHARD" {
-                        if attributes.len() == 1 {
-                            for entry in attributes {
-                                lowestbin_id = format!("{}_{}", id, entry.0.to_uppercase());
-                                lowestbin_price /= 2_i64.pow((entry.1 - 1) as u32) as f32;
-                            }
-                        }
-                    } else {
-                        for entry in attributes {
-                            lowestbin_id.push_str("+ATTRIBUTE_SHARD_");
-                            lowestbin_id.push_str(&entry.0.to_uppercase());
-                        }
-                    }
-                }
-                if id == "PARTY_HAT_CRAB" || id == "PARTY_HAT_CRAB_ANIMATED" {
-                    if let Some(party_hat_color) = &extra_attrs.party_hat_color {
-                        lowestbin_id = format!(
-                            "PARTY_HAT_CRAB_{}{}",
-                            party_hat_color.to_uppercase(),
-                            if id.ends_with("_ANIMATED") {
-                                "_ANIMATED"
-                            } else {
-                                ""
-                            }
-                        );
-                    }
-                } else if id == "PARTY_HAT_SLOTH" {
-                    if let Some(party_hat_emoji) = &extra_attrs.party_hat_emoji {
-                        lowestbin_id = format!("{}_{}", id, party_hat_emoji.to_uppercase());
-                    }
-                } else if id == "NEW_YEAR_CAKE" {
-                    if let Some(new_years_cake) = &extra_attrs.new_years_cake {
-                        lowestbin_id = format!("{}_{}", id, new_years_cake);
-                    }
-                } else if id == "MIDAS_SWORD" || id == "MIDAS_STAFF" {
-                    if let Some(winning_bid) = &extra_attrs.winning_bid {
-                        let best_bid = if id == "MIDAS_SWORD" {
-                            50000000
-                        } else {
-                            100000000
-                        };
-                        if winning_bid > &best_bid {
-                            lowestbin_id = format!("{}_{}", id, best_bid);
-                        }
-                    }
-                } else if id == "RUNE" {
-                    if let Some(runes) = &extra_attrs.runes {
-                        if runes.len() == 1 {
-                            for entry in runes {
-                                lowestbin_id = format!(
-                                    "{}_RUNE;{}",
-                                    entry.key().to_uppercase(),
-                                    entry.value()
-                                );
-                            }
-                        }
-                    }
-                }
-                if extra_attrs.is_shiny() {
-                    lowestbin_id.push_str("_SHINY");
-                }
+            // Auctions created or bid on since the previous run (nothing is new on the first run)
+            let stream_auction =
+                stream_since != 0 && auction.last_updated > stream_since && has_subscribers();
 
+            if has_item_key {
                 if is_full_update {
                     update_lower_else_insert(&lowestbin_id, lowestbin_price, bin_prices);
+                    if let Some(pet_level_id) = &pet_level_id {
+                        update_lower_else_insert(pet_level_id, lowestbin_price, bin_prices);
+                    }
                 }
 
-                if update_underbin
-                    && id != "PET" // TODO: Improve under bins
-                    && !auction.item_lore.contains("Furniture")
-                    && auction.item_name != "null"
-                    && !auction.item_name.contains("Minion Skin")
-                {
-                    if let Some(past_bin_price) = past_bin_prices.get(&lowestbin_id) {
-                        let profit = calculate_with_taxes(*past_bin_price.value())
-                            - auction.starting_bid as f32;
-                        if profit > 1000000.0 {
-                            under_bin_prices.insert(
-                                auction.uuid.clone(),
-                                json!({
-                                    "uuid": auction.uuid,
-                                    "name":  auction.item_name,
-                                    "id" : lowestbin_id,
-                                    "auctioneer":  auction.auctioneer,
-                                    "starting_bid" :  auction.starting_bid,
-                                    "past_bin_price": *past_bin_price.value(),
-                                    "profit": profit
-                                }),
-                            );
+                // Pets are compared against pets of a similar level and the same held item
+                let underbin_id = if id == "PET" {
+                    pet_level_id.clone()
+                } else {
+                    Some(lowestbin_id.to_string())
+                };
+
+                if update_underbin {
+                    if let Some(past_bin_price) =
+                        underbin_id.as_ref().and_then(|id| past_bin_prices.get(id))
+                    {
+                        let price = auction.starting_bid as f32;
+                        let score = UnderbinScore::new(
+                            price,
+                            *past_bin_price.value(),
+                            recent_bin_averages.get(past_bin_price.key()).as_deref(),
+                            underbin_rules,
+                        );
+                        if underbin_rules.is_underbin(&UnderbinCandidate {
+                            id: &lowestbin_id,
+                            name: &auction.item_name,
+                            lore: &auction.item_lore,
+                            category: &auction.category,
+                            price,
+                            profit: score.profit,
+                            confidence: score.confidence,
+                        }) {
+                            let under_bin = json!({
+                                "uuid": auction.uuid,
+                                "name":  auction.item_name,
+                                "id" : underbin_id,
+                                "auctioneer":  auction.auctioneer,
+                                "starting_bid" :  auction.starting_bid,
+                                "past_bin_price": *past_bin_price.value(),
+                                "expected_resale_price": score.expected_resale_price,
+                                "profit": score.profit,
+                                "liquidity": score.liquidity,
+                                "confidence": score.confidence
+                            });
+                            if stream_auction {
+                                publish(StreamEvent {
+                                    event: "underbin",
+                                    item_id: id.to_string(),
+                                    internal_id: lowestbin_id.to_string(),
+                                    tier: Some(tier.to_string()),
+                                    bin: true,
+                                    price: auction.starting_bid,
+                                    data: under_bin.clone(),
+                                });
+                            }
+                            under_bin_prices.insert(auction.uuid.clone(), under_bin);
                         }
                     }
                 }
             }
 
+            if stream_auction {
+                publish(StreamEvent {
+                    event: "auction",
+                    item_id: id.to_string(),
+                    internal_id: lowestbin_id.to_string(),
+                    tier: Some(tier.to_string()),
+                    bin: auction.bin,
+                    price: auction.starting_bid.max(auction.highest_bid_amount),
+                    data: json!({
+                        "uuid": auction.uuid,
+                        "auctioneer": auction.auctioneer,
+                        "end_t": auction.end,
+                        "item_name": auction.item_name,
+                        "tier": tier,
+                        "item_id": id,
+                        "internal_id": lowestbin_id,
+                        "bin": auction.bin,
+                        "starting_bid": auction.starting_bid,
+                        "highest_bid": auction.highest_bid_amount,
+                        "count": nbt.count
+                    }),
+                });
+            }
+
             // Push this auction to the array
             if update_query {
                 let mut bids = Vec::new();
@@ -526,6 +616,7 @@ fn parse_auctions(
                     etherwarp: extra_attrs.is_etherwarp_applied(),
                     necron_scrolls: extra_attrs.ability_scroll.to_owned(),
                     gemstones: extra_attrs.get_gemstones(),
+                    pet_level_id,
                 });
             }
         }
@@ -536,9 +627,12 @@ fn parse_auctions(
 
 /* Parse ended auctions into Vec<AvgAh> */
 async fn parse_ended_auctions(
+    config: &Config,
     avg_ah_prices: &DashMap<String, AvgSum>,
     avg_bin_prices: &DashMap<String, AvgSum>,
+    pet_avg_bin_prices: &DashMap<String, AvgSum>,
     pet_prices: &DashMap<String, AvgSum>,
+    skipped_auctions: &SkippedAuctions,
     update_average_auction: bool,
     update_average_bin: bool,
     update_pets: bool,
@@ -546,17 +640,19 @@ async fn parse_ended_auctions(
     update_ended_auction_uuids: bool,
     started_epoch: &mut i64,
 ) -> bool {
-    match get_ended_auctions().await {
+    match get_ended_auctions(config).await {
         Some(page_request) => {
             *started_epoch = page_request.last_updated;
+            let stream_ended = has_subscribers();
+            let before_page_parse = Instant::now();
 
-            for mut auction in page_request.auctions {
+            for auction in page_request.auctions {
                 if update_ended_auction_uuids {
-                    ended_auction_uuids.insert(auction.auction_id);
+                    ended_auction_uuids.insert(auction.auction_id.to_string());
                 }
 
-                // Always update if pets is enabled, otherwise check if only auction or bin are enabled
-                if !update_pets && !(update_average_auction && update_average_bin) {
+                // Always update if pets is enabled or someone is streaming, otherwise check if only auction or bin are enabled
+                if !(update_pets || stream_ended || update_average_auction && update_average_bin) {
                     // Only update avg ah is enabled but is bin or only update avg bin is enabled but isn't bin
                     if (update_average_auction && auction.bin)
                         || (update_average_bin && !auction.bin)
@@ -565,19 +661,48 @@ async fn parse_ended_auctions(
                     }
                 }
 
-                let nbt = &parse_nbt(&auction.item_bytes).unwrap().i[0];
+                let nbt = match parse_nbt(&auction.item_bytes) {
+                    Ok(nbt) => nbt,
+                    Err(e) => {
+                        skipped_auctions.record(&auction.auction_id, &e);
+                        continue;
+                    }
+                };
                 let extra_attrs = &nbt.tag.extra_attributes;
-                let mut id = extra_attrs.id.to_owned();
-
-                if id == "PET" {
-                    let pet_info =
-                        serde_json::from_str::<PetInfo>(extra_attrs.pet.as_ref().unwrap()).unwrap();
+                let item_key = match ItemKey::from_item(extra_attrs, &nbt.tag.display.name) {
+                    Ok(item_key) => item_key,
+                    Err(e) => {
+                        skipped_auctions.record(&auction.auction_id, &e);
+                        continue;
+                    }
+                };
+
+                if stream_ended {
+                    publish(StreamEvent {
+                        event: "ended_auction",
+                        item_id: extra_attrs.id.to_string(),
+                        internal_id: item_key.internal_id.to_string(),
+                        tier: None,
+                        bin: auction.bin,
+                        price: auction.price,
+                        data: json!({
+                            "uuid": auction.auction_id,
+                            "item_id": extra_attrs.id,
+                            "internal_id": item_key.internal_id,
+                            "bin": auction.bin,
+                            "price": auction.price,
+                            "count": nbt.count
+                        }),
+                    });
+                }
 
-                    let item_name = MC_CODE_REGEX
-                        .replace_all(&nbt.tag.display.name, "")
-                        .to_string();
+                if update_pets && extra_attrs.id == "PET" {
+                    // Already validated when creating the item key
+                    if let Ok(pet_info) = extra_attrs.get_pet_info() {
+                        let item_name = MC_CODE_REGEX
+                            .replace_all(&nbt.tag.display.name, "")
+                            .to_string();
 
-                    if update_pets {
                         let pet_id = format!(
                             "{}_{}{}",
                             item_name.replace(' ', "_").replace("_✦", ""),
@@ -606,110 +731,36 @@ async fn parse_ended_auctions(
                             );
                         }
                     }
-
-                    let mut split = item_name.split("] ");
-                    split.next();
-
-                    id = format!(
-                        "{};{}",
-                        split
-                            .next()
-                            .unwrap()
-                            .replace(' ', "_")
-                            .replace("_✦", "")
-                            .to_uppercase(),
-                        match pet_info.tier.as_str() {
-                            "COMMON" => 0,
-                            "UNCOMMON" => 1,
-                            "RARE" => 2,
-                            "EPIC" => 3,
-                            "LEGENDARY" => 4,
-                            "MYTHIC" => 5,
-                            _ => -1,
-                        }
-                    );
                 }
 
                 if !update_average_bin && !update_average_auction {
                     continue;
                 }
 
-                if let Some(attributes) = &extra_attrs.attributes {
-                    if id == "ATTRIBUTE_SHARD" {
-                        if attributes.len() == 1 {
-                            for entry in attributes {
-                                id = format!("ATTRIBUTE_SHARD_{}", entry.0.to_uppercase());
-                                auction.price /= 2_i64.pow((entry.1 - 1) as u32);
-                            }
-                        }
-                    } else if !attributes.is_empty() {
-                        // Track average of item (regardless of attributes)
-                        if update_average_bin && auction.bin {
-                            update_average_map(avg_bin_prices, &id, auction.price, nbt.count);
-                        } else if update_average_auction && !auction.bin {
-                            update_average_map(avg_ah_prices, &id, auction.price, nbt.count);
-                        }
+                let price = auction.price / item_key.units;
+                let avg_prices = if update_average_bin && auction.bin {
+                    avg_bin_prices
+                } else if update_average_auction && !auction.bin {
+                    avg_ah_prices
+                } else {
+                    continue;
+                };
 
-                        for entry in attributes {
-                            id.push_str("+ATTRIBUTE_SHARD_");
-                            id.push_str(&entry.0.to_uppercase());
-                        }
-                    }
+                // Track average of item (regardless of attributes)
+                if let Some(base_id) = &item_key.base_id {
+                    update_average_map(avg_prices, base_id, price, nbt.count);
                 }
-                if id == "PARTY_HAT_CRAB" || id == "PARTY_HAT_CRAB_ANIMATED" {
-                    if let Some(party_hat_color) = &extra_attrs.party_hat_color {
-                        id = format!(
-                            "PARTY_HAT_CRAB_{}{}",
-                            party_hat_color.to_uppercase(),
-                            if id.ends_with("_ANIMATED") {
-                                "_ANIMATED"
-                            } else {
-                                ""
-                            }
-                        );
-                    }
-                } else if id == "PARTY_HAT_SLOTH" {
-                    if let Some(party_hat_emoji) = &extra_attrs.party_hat_emoji {
-                        id = format!("{}_{}", id, party_hat_emoji.to_uppercase());
-                    }
-                } else if id == "NEW_YEAR_CAKE" {
-                    if let Some(new_years_cake) = &extra_attrs.new_years_cake {
-                        id = format!("{}_{}", id, new_years_cake);
-                    }
-                } else if id == "MIDAS_SWORD" || id == "MIDAS_STAFF" {
-                    if let Some(winning_bid) = &extra_attrs.winning_bid {
-                        let best_bid = if id == "MIDAS_SWORD" {
-                            50000000
-                        } else {
-                            100000000
-                        };
-                        if winning_bid > &best_bid {
-                            id = format!("{}_{}", id, best_bid);
-                        }
-                    }
-                } else if id == "RUNE" {
-                    if let Some(runes) = &extra_attrs.runes {
-                        if runes.len() == 1 {
-                            for entry in runes {
-                                id = format!(
-                                    "{}_RUNE;{}",
-                                    entry.key().to_uppercase(),
-                                    entry.value()
-                                );
-                            }
-                        }
+                update_average_map(avg_prices, &item_key.internal_id, price, nbt.count);
+                // Pet bins are also tracked by level and held item to score pet under bins
+                if auction.bin {
+                    if let Some(pet_level_id) =
+                        item_key.get_pet_level_id(extra_attrs, &nbt.tag.display.name)
+                    {
+                        update_average_map(pet_avg_bin_prices, &pet_level_id, price, nbt.count);
                     }
                 }
-                if extra_attrs.is_shiny() {
-                    id.push_str("_SHINY");
-                }
-
-                if update_average_bin && auction.bin {
-                    update_average_map(avg_bin_prices, &id, auction.price, nbt.count);
-                } else if update_average_auction && !auction.bin {
-                    update_average_map(avg_ah_prices, &id, auction.price, nbt.count);
-                }
             }
+            observe_parse("auctions_ended", before_page_parse);
         }
         None => {
             error(String::from("Failed to fetch ended auctions"));
@@ -720,29 +771,22 @@ async fn parse_ended_auctions(
 }
 
 /* Gets an auction page from the Hypixel API */
-async fn get_auction_page(page_number: i32) -> Option<Auctions> {
-    let res = HTTP_CLIENT
-        .get(format!(
-            "https://api.hypixel.net/skyblock/auctions?page={page_number}"
-        ))
-        .send()
-        .await;
-    if res.is_ok() {
-        res.unwrap().json().await.ok()
-    } else {
-        None
-    }
+async fn get_auction_page(config: &Config, page_number: i32) -> Option<Auctions> {
+    let started = Instant::now();
+    let page = get_upstream(
+        config,
+        &format!("/skyblock/auctions?page={page_number}"),
+        &format!("auctions/{page_number}.json"),
+    )
+    .await;
+    observe_fetch("auctions", started, page.is_some());
+    page
 }
 
 /* Gets ended auctions from the Hypixel API */
-async fn get_ended_auctions() -> Option<EndedAuctions> {
-    let res = HTTP_CLIENT
-        .get("https://api.hypixel.net/skyblock/auctions_ended")
-        .send()
-        .await;
-    if res.is_ok() {
-        res.unwrap().json().await.ok()
-    } else {
-        None
-    }
+async fn get_ended_auctions(config: &Config) -> Option<EndedAuctions> {
+    let started = Instant::now();
+    let page = get_upstream(config, "/skyblock/auctions_ended", "auctions_ended.json").await;
+    observe_fetch("auctions_ended", started, page.is_some());
+    page
 }
@@ -0,0 +1,236 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! API keys stored in the database, each with its own scopes, expiry, and disabled flag
+
+use crate::{
+    statics::{API_KEY_CACHE, DATABASE},
+    utils::get_client,
+};
+use serde::Serialize;
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tokio_postgres::{Error, Row};
+
+/// How long a looked up key is trusted before it is looked up again
+const CACHE_DURATION: Duration = Duration::from_secs(30);
+/// Most keys kept in the cache, so random keys cannot grow it forever
+const CACHE_CAPACITY: usize = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Query,
+    Pets,
+    Lowestbin,
+    Underbin,
+    Averages,
+    Stream,
+    Alerts,
+    RawSql,
+    DebugLogs,
+    Metrics,
+    /// Every scope, including managing keys
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 11] = [
+        Scope::Query,
+        Scope::Pets,
+        Scope::Lowestbin,
+        Scope::Underbin,
+        Scope::Averages,
+        Scope::Stream,
+        Scope::Alerts,
+        Scope::RawSql,
+        Scope::DebugLogs,
+        Scope::Metrics,
+        Scope::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Query => "query",
+            Scope::Pets => "pets",
+            Scope::Lowestbin => "lowestbin",
+            Scope::Underbin => "underbin",
+            Scope::Averages => "averages",
+            Scope::Stream => "stream",
+            Scope::Alerts => "alerts",
+            Scope::RawSql => "raw_sql",
+            Scope::DebugLogs => "debug_logs",
+            Scope::Metrics => "metrics",
+            Scope::Admin => "admin",
+        }
+    }
+
+    /// Scopes that the `API_KEY` does not have, only the `ADMIN_API_KEY`
+    pub fn is_admin_only(&self) -> bool {
+        matches!(
+            self,
+            Scope::Alerts | Scope::RawSql | Scope::DebugLogs | Scope::Metrics | Scope::Admin
+        )
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown scope {}, must be one of {}",
+                    s,
+                    Scope::ALL.map(|scope| scope.as_str()).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id: i32,
+    pub label: String,
+    /// First characters of the key to tell keys apart, the full key is only shown when created
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub disabled: bool,
+}
+
+impl From<Row> for ApiKey {
+    fn from(row: Row) -> Self {
+        Self {
+            id: row.get("id"),
+            label: row.get("label"),
+            key_prefix: row.get("key_prefix"),
+            scopes: row.get("scopes"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+            disabled: row.get("disabled"),
+        }
+    }
+}
+
+impl ApiKey {
+    /// If the key is not disabled or expired at this time (epoch milliseconds)
+    pub fn is_active(&self, now: i64) -> bool {
+        !self.disabled && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    /// If the key can be used for the scope at this time (epoch milliseconds)
+    pub fn allows(&self, scope: Scope, now: i64) -> bool {
+        self.is_active(now)
+            && self
+                .scopes
+                .iter()
+                .any(|s| s == scope.as_str() || s == Scope::Admin.as_str())
+    }
+}
+
+/// Finds a key in the database. Lookups are cached, so changes made by another instance can
+/// take up to 30 seconds to apply
+pub async fn get_api_key(key: &str) -> Option<ApiKey> {
+    if let Some(cached) = API_KEY_CACHE.get(key) {
+        if cached.1.elapsed() < CACHE_DURATION {
+            return cached.0.clone();
+        }
+    }
+
+    let pool = DATABASE.lock().await.clone()?;
+    let row = pool
+        .get()
+        .await
+        .ok()?
+        .query_opt(
+            "SELECT * FROM api_keys WHERE key_hash = sha256(convert_to($1, 'UTF8'))",
+            &[&key],
+        )
+        .await
+        .ok()?;
+    let api_key = row.map(ApiKey::from);
+
+    if API_KEY_CACHE.len() >= CACHE_CAPACITY {
+        API_KEY_CACHE.clear();
+    }
+    API_KEY_CACHE.insert(key.to_string(), (api_key.clone(), Instant::now()));
+
+    api_key
+}
+
+/// Creates a random key, returning it with the full key. Only its hash is stored
+pub async fn create_api_key(
+    label: &str,
+    scopes: &[Scope],
+    created_at: i64,
+    expires_at: Option<i64>,
+) -> Result<(ApiKey, String), Error> {
+    let row = get_client()
+        .await
+        .query_one(
+            "WITH new_key AS (SELECT replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', '') AS key)
+            INSERT INTO api_keys (key_hash, key_prefix, label, scopes, created_at, expires_at)
+            SELECT sha256(convert_to(key, 'UTF8')), left(key, 8), $1, $2, $3, $4 FROM new_key
+            RETURNING *, (SELECT key FROM new_key) AS key",
+            &[
+                &label,
+                &scopes.iter().map(Scope::as_str).collect::<Vec<&str>>(),
+                &created_at,
+                &expires_at,
+            ],
+        )
+        .await?;
+    let key = row.get("key");
+
+    Ok((ApiKey::from(row), key))
+}
+
+pub async fn list_api_keys() -> Result<Vec<ApiKey>, Error> {
+    let rows = get_client()
+        .await
+        .query("SELECT * FROM api_keys ORDER BY id", &[])
+        .await?;
+
+    Ok(rows.into_iter().map(ApiKey::from).collect())
+}
+
+/// Disables a key, returning if it exists
+pub async fn revoke_api_key(id: i32) -> Result<bool, Error> {
+    let revoked = get_client()
+        .await
+        .execute("UPDATE api_keys SET disabled = true WHERE id = $1", &[&id])
+        .await?;
+
+    // The revoked key could be cached under any key, so forget them all
+    API_KEY_CACHE.clear();
+
+    Ok(revoked > 0)
+}
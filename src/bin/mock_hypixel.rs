@@ -0,0 +1,31 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use dotenv::dotenv;
+use query_api::mock::{start_mock_server, MockConfig};
+use simplelog::{LevelFilter, SimpleLogger};
+use std::{error::Error, sync::Arc};
+
+/* Serves recorded Hypixel auction pages from disk */
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let _ = dotenv();
+    let _ = SimpleLogger::init(LevelFilter::Info, simplelog::Config::default());
+
+    start_mock_server(Arc::new(MockConfig::load_or_panic())).await
+}
@@ -16,7 +16,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashSet, env, str::FromStr};
+use crate::{
+    notifier::{NotifierConfig, Severity},
+    rate_limit::RateLimits,
+    underbin::UnderbinRules,
+};
+use std::{collections::HashSet, env, fs, str::FromStr};
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Feature {
@@ -26,6 +31,7 @@ pub enum Feature {
     Underbin,
     AverageAuction,
     AverageBin,
+    Alerts,
 }
 
 impl FromStr for Feature {
@@ -39,18 +45,49 @@ impl FromStr for Feature {
             "UNDERBIN" => Self::Underbin,
             "AVERAGE_AUCTION" => Self::AverageAuction,
             "AVERAGE_BIN" => Self::AverageBin,
+            "ALERTS" => Self::Alerts,
             _ => return Err(format!("Unknown feature flag {}", s)),
         })
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SnapshotMode {
+    Record,
+    Replay,
+}
+
+impl FromStr for SnapshotMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "RECORD" => Self::Record,
+            "REPLAY" => Self::Replay,
+            _ => return Err(format!("Unknown snapshot mode {}", s)),
+        })
+    }
+}
+
 pub struct Config {
     pub enabled_features: HashSet<Feature>,
     pub webhook_url: String,
+    pub notifiers: Vec<NotifierConfig>,
+    pub notification_window_ms: u64,
+    pub usage_retention_days: i64,
+    pub raw_query_timeout_ms: u64,
+    pub raw_query_row_limit: i64,
+    pub raw_query_concurrency: usize,
+    pub stream_max_subscribers: usize,
     pub base_url: String,
     pub port: u32,
     pub full_url: String,
     pub postgres_url: String,
+    pub hypixel_api_url: String,
+    pub snapshot_mode: Option<SnapshotMode>,
+    pub snapshot_dir: String,
+    pub underbin_rules: UnderbinRules,
+    pub rate_limits: RateLimits,
     pub api_key: String,
     pub admin_api_key: String,
     pub debug: bool,
@@ -69,6 +106,71 @@ impl Config {
         let port = get_env("PORT").parse::<u32>().expect("PORT not valid");
         let api_key = env::var("API_KEY").unwrap_or_default();
         let webhook_url = env::var("WEBHOOK_URL").unwrap_or_default();
+        let mut notifiers = Vec::new();
+        if !webhook_url.is_empty() {
+            notifiers.push(NotifierConfig::Discord {
+                url: webhook_url.clone(),
+                severity: Severity::Info,
+                mention: env::var("WEBHOOK_MENTION")
+                    .ok()
+                    .filter(|mention| !mention.is_empty()),
+            });
+        }
+        let raw_query_timeout_ms = env::var("RAW_QUERY_TIMEOUT_MS")
+            .ok()
+            .filter(|timeout| !timeout.is_empty())
+            .map(|timeout| {
+                timeout
+                    .parse::<u64>()
+                    .expect("RAW_QUERY_TIMEOUT_MS not valid")
+            })
+            .unwrap_or(5000);
+        let raw_query_row_limit = env::var("RAW_QUERY_ROW_LIMIT")
+            .ok()
+            .filter(|limit| !limit.is_empty())
+            .map(|limit| limit.parse::<i64>().expect("RAW_QUERY_ROW_LIMIT not valid"))
+            .unwrap_or(10000);
+        let raw_query_concurrency = env::var("RAW_QUERY_CONCURRENCY")
+            .ok()
+            .filter(|concurrency| !concurrency.is_empty())
+            .map(|concurrency| {
+                concurrency
+                    .parse::<usize>()
+                    .expect("RAW_QUERY_CONCURRENCY not valid")
+            })
+            .unwrap_or(2);
+        let stream_max_subscribers = env::var("STREAM_MAX_SUBSCRIBERS")
+            .ok()
+            .filter(|max| !max.is_empty())
+            .map(|max| {
+                max.parse::<usize>()
+                    .expect("STREAM_MAX_SUBSCRIBERS not valid")
+            })
+            .unwrap_or(100);
+        let notification_window_ms = env::var("NOTIFICATION_WINDOW_MS")
+            .ok()
+            .filter(|window| !window.is_empty())
+            .map(|window| {
+                window
+                    .parse::<u64>()
+                    .expect("NOTIFICATION_WINDOW_MS not valid")
+            })
+            .unwrap_or(2000);
+        let usage_retention_days = env::var("USAGE_RETENTION_DAYS")
+            .ok()
+            .filter(|days| !days.is_empty())
+            .map(|days| days.parse::<i64>().expect("USAGE_RETENTION_DAYS not valid"))
+            .unwrap_or(30);
+        let notifiers_path = env::var("NOTIFIERS").unwrap_or_default();
+        if !notifiers_path.is_empty() {
+            let file = fs::read_to_string(&notifiers_path)
+                .unwrap_or_else(|e| panic!("Unable to read notifiers {}: {}", notifiers_path, e));
+            notifiers.extend(
+                serde_json::from_str::<Vec<NotifierConfig>>(&file).unwrap_or_else(|e| {
+                    panic!("Unable to parse notifiers {}: {}", notifiers_path, e)
+                }),
+            );
+        }
         let admin_api_key = env::var("ADMIN_API_KEY").unwrap_or_else(|_| api_key.clone());
         let debug = env::var("DEBUG")
             .unwrap_or_else(|_| String::from("false"))
@@ -83,6 +185,20 @@ impl Config {
             .parse()
             .unwrap_or(false);
         let postgres_url = get_env("POSTGRES_URL");
+        let hypixel_api_url = env::var("HYPIXEL_API_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| String::from("https://api.hypixel.net"))
+            .trim_end_matches('/')
+            .to_string();
+        let snapshot_mode = env::var("SNAPSHOT_MODE")
+            .ok()
+            .filter(|mode| !mode.is_empty())
+            .map(|mode| SnapshotMode::from_str(&mode).unwrap());
+        let snapshot_dir = env::var("SNAPSHOT_DIR").unwrap_or_else(|_| String::from("snapshots"));
+        let underbin_rules =
+            UnderbinRules::load_or_panic(&env::var("UNDERBIN_RULES").unwrap_or_default());
+        let rate_limits = RateLimits::load_or_panic(&env::var("RATE_LIMITS").unwrap_or_default());
         let features = get_env("FEATURES")
             .replace(',', "+")
             .split('+')
@@ -91,6 +207,9 @@ impl Config {
         if features.contains(&Feature::Lowestbin) && !features.contains(&Feature::Query) {
             panic!("The QUERY feature must be enabled to enable the LOWESTBIN feature");
         }
+        if features.contains(&Feature::Alerts) && !features.contains(&Feature::Query) {
+            panic!("The QUERY feature must be enabled to enable the ALERTS feature");
+        }
         if features.contains(&Feature::Underbin) && !features.contains(&Feature::Lowestbin) {
             panic!("The LOWESTBIN feature must be enabled to enable the UNDERBIN feature");
         }
@@ -98,8 +217,20 @@ impl Config {
             enabled_features: features,
             full_url: format!("{}:{}", base_url, port),
             postgres_url,
+            hypixel_api_url,
+            snapshot_mode,
+            snapshot_dir,
+            underbin_rules,
+            rate_limits,
             base_url,
             webhook_url,
+            notifiers,
+            notification_window_ms,
+            usage_retention_days,
+            raw_query_timeout_ms,
+            raw_query_row_limit,
+            raw_query_concurrency,
+            stream_max_subscribers,
             api_key,
             admin_api_key,
             port,
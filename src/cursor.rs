@@ -0,0 +1,109 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Keyset pagination cursors for `/query`
+
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+/// Position after the last auction of a page. Auctions are ordered by the sort column and then
+/// by uuid, so the next page starts after this pair
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueryCursor {
+    /// Column sorted by, or uuid if the results are not sorted
+    pub sort_by: String,
+    pub descending: bool,
+    /// Value of the sort column, none when sorting by uuid
+    pub value: Option<i64>,
+    pub uuid: String,
+}
+
+impl QueryCursor {
+    /// Creates a cursor that continues after the auction. The row must have the uuid and sort columns
+    pub fn after(row: &Row, sort_by: &str, descending: bool) -> QueryCursor {
+        QueryCursor {
+            sort_by: sort_by.to_string(),
+            descending,
+            value: if sort_by == "uuid" {
+                None
+            } else {
+                row.get(sort_by)
+            },
+            uuid: row.get("uuid"),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    /// Decodes a cursor and checks that it was created with the same sort
+    pub fn decode(cursor: &str, sort_by: &str, descending: bool) -> Result<QueryCursor, String> {
+        let cursor = general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<QueryCursor>(&bytes).ok())
+            .ok_or_else(|| String::from("Invalid cursor"))?;
+
+        if cursor.sort_by != sort_by
+            || cursor.descending != descending
+            || cursor.value.is_none() != (sort_by == "uuid")
+        {
+            return Err(String::from(
+                "The cursor was created with a different sort_by or sort_order",
+            ));
+        }
+
+        Ok(cursor)
+    }
+}
+
+/// A page of auctions and the cursor of the next page, if there is one
+#[derive(Serialize)]
+pub struct QueryPage<T> {
+    pub auctions: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> QueryPage<T> {
+    /// Builds a page from up to limit + 1 rows, the extra row only shows there is a next page
+    pub fn new<F>(
+        mut rows: Vec<Row>,
+        limit: i64,
+        sort_by: &str,
+        descending: bool,
+        convert: F,
+    ) -> QueryPage<T>
+    where
+        F: Fn(Row) -> T,
+    {
+        let mut next_cursor = None;
+        if rows.len() as i64 > limit {
+            rows.truncate(limit as usize);
+            next_cursor = rows
+                .last()
+                .map(|row| QueryCursor::after(row, sort_by, descending).encode());
+        }
+
+        QueryPage {
+            auctions: rows.into_iter().map(convert).collect(),
+            next_cursor,
+        }
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::statics::METRICS;
+use dashmap::DashMap;
+use log::warn;
+use serde_json::{json, Value};
+use std::{cmp::Reverse, fmt};
+use tokio_postgres::error::{ErrorPosition, SqlState};
+
+#[derive(Debug)]
+pub enum QueryApiError {
+    InvalidItemBytes,
+    MissingItem,
+    MissingPetInfo,
+    InvalidPetInfo(serde_json::Error),
+    InvalidPetName(String),
+}
+
+impl QueryApiError {
+    /// Short reason used to group errors in summaries
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::InvalidItemBytes => "invalid item bytes",
+            Self::MissingItem => "missing item",
+            Self::MissingPetInfo => "missing pet info",
+            Self::InvalidPetInfo(_) => "invalid pet info",
+            Self::InvalidPetName(_) => "invalid pet name",
+        }
+    }
+}
+
+impl fmt::Display for QueryApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidItemBytes => write!(f, "Unable to decode item bytes"),
+            Self::MissingItem => write!(f, "Item bytes do not contain an item"),
+            Self::MissingPetInfo => write!(f, "Pet is missing petInfo"),
+            Self::InvalidPetInfo(e) => write!(f, "Unable to parse petInfo: {}", e),
+            Self::InvalidPetName(name) => write!(f, "Unable to parse pet name from {}", name),
+        }
+    }
+}
+
+impl std::error::Error for QueryApiError {}
+
+#[derive(Debug)]
+pub enum RawQueryError {
+    Busy,
+    RowLimit(i64),
+    Database(tokio_postgres::Error),
+    /// Error running the query, with how many characters were added before the admin's SQL
+    Query(tokio_postgres::Error, u32),
+}
+
+impl RawQueryError {
+    /// Short name of the error that clients can match on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Busy => "busy",
+            Self::RowLimit(_) => "row_limit",
+            Self::Database(e) | Self::Query(e, _) => match e.code() {
+                Some(code) if code == &SqlState::QUERY_CANCELED => "timeout",
+                Some(code) if code == &SqlState::LOCK_NOT_AVAILABLE => "lock_timeout",
+                Some(code) if code == &SqlState::READ_ONLY_SQL_TRANSACTION => "read_only",
+                // Class 42 is syntax errors and access rule violations
+                Some(code) if code.code().starts_with("42") => "invalid_query",
+                _ => "database",
+            },
+        }
+    }
+
+    /// If the error was caused by the query rather than the server
+    pub fn is_client_error(&self) -> bool {
+        !matches!(self.kind(), "busy" | "database")
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (db_error, offset) = match self {
+            Self::Database(e) => (e.as_db_error(), 0),
+            Self::Query(e, offset) => (e.as_db_error(), *offset),
+            _ => (None, 0),
+        };
+
+        json!({
+            "success": false,
+            "reason": self.to_string(),
+            "error": {
+                "kind": self.kind(),
+                "sqlstate": db_error.map(|e| e.code().code()),
+                "detail": db_error.and_then(|e| e.detail()),
+                "hint": db_error.and_then(|e| e.hint()),
+                // Position in the admin's SQL, starting at 1
+                "position": db_error.and_then(|e| match e.position() {
+                    Some(ErrorPosition::Original(position)) if *position > offset => {
+                        Some(position - offset)
+                    }
+                    _ => None,
+                }),
+            }
+        })
+    }
+}
+
+impl fmt::Display for RawQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Busy => write!(f, "Too many raw queries are running, try again later"),
+            Self::RowLimit(limit) => write!(
+                f,
+                "Query returned more than {} rows, add a LIMIT to the query",
+                limit
+            ),
+            Self::Database(e) | Self::Query(e, _) => match e.as_db_error() {
+                Some(db_error) => write!(f, "{}", db_error.message()),
+                None => write!(f, "{}", e),
+            },
+        }
+    }
+}
+
+impl std::error::Error for RawQueryError {}
+
+impl From<tokio_postgres::Error> for RawQueryError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+/* Counts auctions that were skipped during a run, grouped by reason */
+#[derive(Default)]
+pub struct SkippedAuctions {
+    counts: DashMap<&'static str, u32>,
+}
+
+impl SkippedAuctions {
+    pub fn record(&self, uuid: &str, error: &QueryApiError) {
+        warn!("Skipping auction {}: {}", uuid, error);
+        METRICS
+            .auctions_skipped
+            .with_label_values(&[error.reason()])
+            .inc();
+        *self.counts.entry(error.reason()).or_insert(0) += 1;
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts.iter().map(|ele| *ele.value()).sum()
+    }
+
+    /// Summary of skipped auctions for the run logs, or None if nothing was skipped
+    pub fn summary(&self) -> Option<String> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+
+        let mut reasons = self
+            .counts
+            .iter()
+            .map(|ele| (*ele.key(), *ele.value()))
+            .collect::<Vec<(&str, u32)>>();
+        reasons.sort_by_key(|reason| Reverse(reason.1));
+
+        Some(format!(
+            "Skipped {} auctions ({})",
+            total,
+            reasons
+                .iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    }
+}
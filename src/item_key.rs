@@ -0,0 +1,187 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{error::QueryApiError, statics::MC_CODE_REGEX, structs::PartialExtraAttr};
+
+/// The key an item is tracked under for lowest bins and averages
+#[derive(Debug, PartialEq, Eq)]
+pub struct ItemKey {
+    /// Internal id of the item (e.g. ENDER_DRAGON;4 or TERROR_BOOTS+ATTRIBUTE_SHARD_MANA_POOL)
+    pub internal_id: String,
+    /// Internal id without attribute suffixes, only set if the item has attributes
+    pub base_id: Option<String>,
+    /// Number of level one items this item is worth. Prices should be divided by this
+    pub units: i64,
+}
+
+impl ItemKey {
+    /// Derives the key from the item's extra attributes and display name (color codes are ignored)
+    pub fn from_item(
+        extra_attrs: &PartialExtraAttr,
+        display_name: &str,
+    ) -> Result<Self, QueryApiError> {
+        let id = extra_attrs.id.as_str();
+        let mut internal_id = id.to_string();
+        let mut base_id = None;
+        let mut units = 1;
+
+        if id == "PET" {
+            let pet_info = extra_attrs.get_pet_info()?;
+            let item_name = MC_CODE_REGEX.replace_all(display_name, "");
+
+            let mut split = item_name.split("] ");
+            split.next();
+            let pet_name = split
+                .next()
+                .ok_or_else(|| QueryApiError::InvalidPetName(item_name.to_string()))?;
+
+            internal_id = format!(
+                "{};{}",
+                pet_name.replace(' ', "_").replace("_✦", "").to_uppercase(),
+                get_tier_index(&pet_info.tier)
+            );
+        }
+
+        if let Some(attributes) = &extra_attrs.attributes {
+            if id == "ATTRIBUTE_SHARD" {
+                if attributes.len() == 1 {
+                    for entry in attributes {
+                        internal_id = format!("{}_{}", id, entry.0.to_uppercase());
+                        // Each level of a shard is worth two of the previous level
+                        units = 2_i64.pow((entry.1 - 1).max(0) as u32);
+                    }
+                }
+            } else if !attributes.is_empty() {
+                base_id = Some(internal_id.to_string());
+                for entry in attributes {
+                    internal_id.push_str("+ATTRIBUTE_SHARD_");
+                    internal_id.push_str(&entry.0.to_uppercase());
+                }
+            }
+        }
+
+        if id == "PARTY_HAT_CRAB" || id == "PARTY_HAT_CRAB_ANIMATED" {
+            if let Some(party_hat_color) = &extra_attrs.party_hat_color {
+                internal_id = format!(
+                    "PARTY_HAT_CRAB_{}{}",
+                    party_hat_color.to_uppercase(),
+                    if id.ends_with("_ANIMATED") {
+                        "_ANIMATED"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        } else if id == "PARTY_HAT_SLOTH" {
+            if let Some(party_hat_emoji) = &extra_attrs.party_hat_emoji {
+                internal_id = format!("{}_{}", id, party_hat_emoji.to_uppercase());
+            }
+        } else if id == "NEW_YEAR_CAKE" {
+            if let Some(new_years_cake) = &extra_attrs.new_years_cake {
+                internal_id = format!("{}_{}", id, new_years_cake);
+            }
+        } else if id == "MIDAS_SWORD" || id == "MIDAS_STAFF" {
+            if let Some(winning_bid) = &extra_attrs.winning_bid {
+                let best_bid = if id == "MIDAS_SWORD" {
+                    50000000
+                } else {
+                    100000000
+                };
+                if winning_bid > &best_bid {
+                    internal_id = format!("{}_{}", id, best_bid);
+                }
+            }
+        } else if id == "RUNE" {
+            if let Some(runes) = &extra_attrs.runes {
+                if runes.len() == 1 {
+                    for entry in runes {
+                        internal_id =
+                            format!("{}_RUNE;{}", entry.key().to_uppercase(), entry.value());
+                    }
+                }
+            }
+        }
+
+        if extra_attrs.is_shiny() {
+            internal_id.push_str("_SHINY");
+        }
+
+        Ok(ItemKey {
+            internal_id,
+            base_id,
+            units,
+        })
+    }
+
+    /// Key a pet is compared under for under bins (e.g. ENDER_DRAGON;4+LVL_100+PET_ITEM_TIER_BOOST).
+    /// None if the item is not a pet or its level can't be read from the display name
+    pub fn get_pet_level_id(
+        &self,
+        extra_attrs: &PartialExtraAttr,
+        display_name: &str,
+    ) -> Option<String> {
+        if extra_attrs.id != "PET" {
+            return None;
+        }
+
+        let pet_info = extra_attrs.get_pet_info().ok()?;
+        let item_name = MC_CODE_REGEX.replace_all(display_name, "");
+        let level = item_name
+            .strip_prefix("[Lvl ")?
+            .split(']')
+            .next()?
+            .parse::<i32>()
+            .ok()?;
+
+        let mut pet_level_id = format!("{}+LVL_{}", self.internal_id, get_pet_level_bucket(level));
+        // Held items change the price (a tier boost is worth more than the rarity it skips)
+        if let Some(held_item) = pet_info.held_item {
+            pet_level_id.push('+');
+            pet_level_id.push_str(&held_item);
+        }
+
+        Some(pet_level_id)
+    }
+}
+
+/// Pet level ids are only used to score pet under bins, so they are left out of the lowest bin and
+/// average endpoints
+pub fn is_pet_level_id(id: &str) -> bool {
+    id.contains("+LVL_")
+}
+
+/// Rounds a pet level down to the nearest level pets are usually priced at
+pub fn get_pet_level_bucket(level: i32) -> i32 {
+    [200, 150, 100, 90, 80, 50]
+        .into_iter()
+        .find(|bucket| level >= *bucket)
+        .unwrap_or(1)
+}
+
+/// Converts a rarity into the number used in pet internal ids (COMMON is 0)
+pub fn get_tier_index(tier: &str) -> i32 {
+    match tier {
+        "COMMON" => 0,
+        "UNCOMMON" => 1,
+        "RARE" => 2,
+        "EPIC" => 3,
+        "LEGENDARY" => 4,
+        "MYTHIC" => 5,
+        _ => -1,
+    }
+}
@@ -18,10 +18,24 @@
 
 #![allow(clippy::too_many_arguments)]
 
+pub mod alerts;
 pub mod api_handler;
+pub mod api_keys;
 pub mod config;
+pub mod cursor;
+pub mod error;
+pub mod item_key;
+pub mod metrics;
+pub mod mock;
+pub mod notifier;
+pub mod query_filter;
+pub mod rate_limit;
 pub mod server;
+pub mod snapshot;
 pub mod statics;
+pub mod stream;
 pub mod structs;
+pub mod underbin;
+pub mod usage;
 pub mod utils;
 pub mod webhook;
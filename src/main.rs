@@ -20,18 +20,17 @@ use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
 use dotenv::dotenv;
 use query_api::{
     api_handler::update_auctions,
-    config::{Config, Feature},
+    config::{Config, Feature, SnapshotMode},
+    notifier::start_notification_queue,
+    rate_limit::start_rate_limit_pruner,
     server::start_server,
-    statics::{BID_ARRAY, DATABASE, WEBHOOK},
-    utils::{info, start_auction_loop},
-    webhook::Webhook,
+    snapshot::replay_snapshots,
+    statics::{BID_ARRAY, DATABASE, RAW_QUERY_PERMITS},
+    usage::start_usage_recorder,
+    utils::{get_client, info, start_auction_loop},
 };
 use simplelog::{CombinedLogger, LevelFilter, SimpleLogger, WriteLogger};
-use std::{
-    error::Error,
-    fs::{self, File},
-    sync::Arc,
-};
+use std::{error::Error, fs::File, sync::Arc, time::Duration};
 use tokio_postgres::NoTls;
 
 /* Entry point to the program. Creates loggers, reads config, creates tables, starts auction loop and server */
@@ -63,12 +62,16 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         println!("Loggers Created");
     }
 
-    if !config.webhook_url.is_empty() {
-        let _ = WEBHOOK
-            .lock()
-            .await
-            .insert(Webhook::from_url(config.webhook_url.as_str()));
-    }
+    RAW_QUERY_PERMITS.add_permits(config.raw_query_concurrency);
+    start_rate_limit_pruner();
+    start_notification_queue(
+        config
+            .notifiers
+            .iter()
+            .map(|notifier_config| notifier_config.build())
+            .collect(),
+        Duration::from_millis(config.notification_window_ms),
+    );
 
     if config.is_enabled(Feature::Query)
         || config.is_enabled(Feature::AverageAuction)
@@ -94,6 +97,41 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             .get()
             .await?;
 
+        // Create API keys table if doesn't exist
+        let _ = database
+            .simple_query(
+                "CREATE TABLE IF NOT EXISTS api_keys (
+                        id SERIAL PRIMARY KEY,
+                        key_hash BYTEA NOT NULL UNIQUE,
+                        key_prefix TEXT NOT NULL,
+                        label TEXT NOT NULL,
+                        scopes TEXT[] NOT NULL,
+                        created_at BIGINT NOT NULL,
+                        expires_at BIGINT,
+                        disabled BOOLEAN NOT NULL DEFAULT false
+                    )",
+            )
+            .await?;
+
+        // Create API usage table if doesn't exist
+        let _ = database
+            .simple_query(
+                "CREATE TABLE IF NOT EXISTS api_usage (
+                        time_t BIGINT NOT NULL,
+                        key_name TEXT NOT NULL,
+                        endpoint TEXT NOT NULL,
+                        status SMALLINT NOT NULL,
+                        requests BIGINT NOT NULL,
+                        total_latency_ms DOUBLE PRECISION NOT NULL,
+                        max_latency_ms DOUBLE PRECISION NOT NULL,
+                        response_bytes BIGINT NOT NULL,
+                        latency_buckets BIGINT[] NOT NULL
+                    );
+                    CREATE INDEX IF NOT EXISTS api_usage_time_t_idx ON api_usage (time_t)",
+            )
+            .await?;
+        start_usage_recorder(config.usage_retention_days);
+
         if config.is_enabled(Feature::Query) {
             // Create bid custom type
             let _ = database
@@ -151,7 +189,108 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                             art_of_peace BOOLEAN,
                             etherwarp BOOLEAN,
                             necron_scrolls TEXT[],
-                            gemstones TEXT[]
+                            gemstones TEXT[],
+                            pet_level_id TEXT
+                        )",
+                )
+                .await?;
+            // Added after the table was first released
+            let _ = database
+                .simple_query("ALTER TABLE query ADD COLUMN IF NOT EXISTS pet_level_id TEXT")
+                .await?;
+
+            // Create query items table if doesn't exist
+            let _ = database
+                .simple_query(
+                    "CREATE TABLE IF NOT EXISTS query_items (
+                            name TEXT NOT NULL PRIMARY KEY
+                        )",
+                )
+                .await?;
+        }
+
+        if config.is_enabled(Feature::Lowestbin) {
+            // Create lowest bins table if doesn't exist
+            let _ = database
+                .simple_query(
+                    "CREATE TABLE IF NOT EXISTS lowestbin (
+                            item_id TEXT NOT NULL PRIMARY KEY,
+                            price REAL
+                        )",
+                )
+                .await?;
+
+            // Create pet level lowest bins table if doesn't exist
+            let _ = database
+                .simple_query(
+                    "CREATE TABLE IF NOT EXISTS pet_lowestbin (
+                            item_id TEXT NOT NULL PRIMARY KEY,
+                            price REAL
+                        )",
+                )
+                .await?;
+
+            // Create lowest bin history table if doesn't exist
+            let _ = database
+                .simple_query(
+                    "CREATE TABLE IF NOT EXISTS lowestbin_history (
+                            time_t INT,
+                            item_id TEXT,
+                            price REAL,
+                            PRIMARY KEY (time_t, item_id)
+                        )",
+                )
+                .await?;
+
+            let _ = database
+                .simple_query(
+                    "CREATE INDEX IF NOT EXISTS lowestbin_history_item_id_idx ON lowestbin_history (item_id)",
+                )
+                .await?;
+        }
+
+        if config.is_enabled(Feature::Underbin) {
+            // Create under bins table if doesn't exist
+            let _ = database
+                .simple_query(
+                    "CREATE TABLE IF NOT EXISTS underbin (
+                            uuid TEXT NOT NULL PRIMARY KEY,
+                            data JSONB
+                        )",
+                )
+                .await?;
+        }
+
+        if config.is_enabled(Feature::Alerts) {
+            // Create alert rules table if doesn't exist
+            let _ = database
+                .simple_query(
+                    "CREATE TABLE IF NOT EXISTS alerts (
+                            id SERIAL PRIMARY KEY,
+                            name TEXT NOT NULL,
+                            webhook_url TEXT NOT NULL,
+                            webhook_type TEXT NOT NULL,
+                            item_id TEXT,
+                            internal_id TEXT,
+                            tier TEXT,
+                            bin BOOLEAN,
+                            max_price BIGINT,
+                            min_stars SMALLINT,
+                            recombobulated BOOLEAN,
+                            enchants TEXT[] NOT NULL DEFAULT '{}',
+                            created_t BIGINT NOT NULL
+                        )",
+                )
+                .await?;
+
+            // Create sent alerts table if doesn't exist
+            let _ = database
+                .simple_query(
+                    "CREATE TABLE IF NOT EXISTS alert_sent (
+                            alert_id INT REFERENCES alerts (id) ON DELETE CASCADE,
+                            uuid TEXT,
+                            time_t BIGINT,
+                            PRIMARY KEY (alert_id, uuid)
                         )",
                 )
                 .await?;
@@ -218,6 +357,19 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                         "CREATE INDEX IF NOT EXISTS average_bin_item_id_idx ON average_bin (item_id)",
                     )
                     .await?;
+
+                // Create pet level average bins table if doesn't exist
+                let _ = database
+                    .simple_query(
+                        "CREATE TABLE IF NOT EXISTS pet_average_bin (
+                                time_t INT,
+                                item_id TEXT,
+                                price REAL,
+                                sales REAL,
+                                PRIMARY KEY (time_t, item_id)
+                            )",
+                    )
+                    .await?;
             }
         }
 
@@ -236,25 +388,33 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     }
 
     if !config.disable_updating {
-        // Remove any files from previous runs
-        let _ = fs::remove_file("lowestbin.json");
-        let _ = fs::remove_file("underbin.json");
-        let _ = fs::remove_file("query_items.json");
+        // Remove any under bins from previous runs
+        if config.is_enabled(Feature::Underbin) {
+            let _ = get_client()
+                .await
+                .simple_query("DELETE FROM underbin")
+                .await;
+        }
 
-        info(String::from("Starting auction loop..."));
-        let auction_config = config.clone();
-        start_auction_loop(move || {
-            let auction_config = auction_config.clone();
-            async move {
-                loop {
-                    let auction_config = auction_config.clone();
-                    if update_auctions(auction_config).await {
-                        break;
+        if config.snapshot_mode == Some(SnapshotMode::Replay) {
+            // Replayed snapshots run back to back instead of waiting for API updates
+            tokio::spawn(replay_snapshots(config.clone()));
+        } else {
+            info(String::from("Starting auction loop..."));
+            let auction_config = config.clone();
+            start_auction_loop(config.clone(), move || {
+                let auction_config = auction_config.clone();
+                async move {
+                    loop {
+                        let auction_config = auction_config.clone();
+                        if update_auctions(auction_config).await {
+                            break;
+                        }
                     }
                 }
-            }
-        })
-        .await;
+            })
+            .await;
+        }
     }
 
     info(String::from("Starting server..."));
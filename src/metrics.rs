@@ -0,0 +1,233 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Prometheus metrics for the update loop, webhooks, the database pool, and the server
+
+use crate::statics::{DATABASE, METRICS};
+use prometheus::{
+    exponential_buckets, histogram_opts, opts, Encoder, HistogramVec, IntCounter, IntCounterVec,
+    IntGaugeVec, Registry, TextEncoder,
+};
+use std::time::{Duration, Instant};
+
+pub struct Metrics {
+    registry: Registry,
+    /// Wall time of the fetch (which includes parsing), insert, and whole update
+    pub update_seconds: HistogramVec,
+    /// Time to fetch each page from the auctions or auctions_ended endpoint
+    pub page_fetch_seconds: HistogramVec,
+    /// Time to parse each page
+    pub page_parse_seconds: HistogramVec,
+    pub pages_fetched: IntCounterVec,
+    pub upstream_failures: IntCounterVec,
+    pub auctions_inserted: IntCounter,
+    pub auctions_skipped: IntCounterVec,
+    pub webhook_failures: IntCounter,
+    /// Set from the pool status when metrics are gathered
+    pub database_connections: IntGaugeVec,
+    pub http_request_seconds: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let update_seconds = HistogramVec::new(
+            histogram_opts!(
+                "query_api_update_seconds",
+                "Time spent in each phase of an update",
+                exponential_buckets(0.25, 2.0, 10).unwrap()
+            ),
+            &["phase"],
+        )
+        .unwrap();
+        let page_fetch_seconds = HistogramVec::new(
+            histogram_opts!(
+                "query_api_page_fetch_seconds",
+                "Time to fetch a page from the Hypixel API",
+                exponential_buckets(0.025, 2.0, 10).unwrap()
+            ),
+            &["source"],
+        )
+        .unwrap();
+        let page_parse_seconds = HistogramVec::new(
+            histogram_opts!(
+                "query_api_page_parse_seconds",
+                "Time to parse the auctions of a page",
+                exponential_buckets(0.001, 2.0, 12).unwrap()
+            ),
+            &["source"],
+        )
+        .unwrap();
+        let pages_fetched = IntCounterVec::new(
+            opts!(
+                "query_api_pages_fetched_total",
+                "Pages fetched from the Hypixel API"
+            ),
+            &["source"],
+        )
+        .unwrap();
+        let upstream_failures = IntCounterVec::new(
+            opts!(
+                "query_api_upstream_failures_total",
+                "Pages that could not be fetched or parsed from the Hypixel API"
+            ),
+            &["source"],
+        )
+        .unwrap();
+        let auctions_inserted = IntCounter::new(
+            "query_api_auctions_inserted_total",
+            "Auctions inserted into the query table",
+        )
+        .unwrap();
+        let auctions_skipped = IntCounterVec::new(
+            opts!(
+                "query_api_auctions_skipped_total",
+                "Auctions skipped because they could not be parsed"
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        let webhook_failures = IntCounter::new(
+            "query_api_webhook_failures_total",
+            "Webhooks that could not be delivered",
+        )
+        .unwrap();
+        let database_connections = IntGaugeVec::new(
+            opts!(
+                "query_api_database_connections",
+                "Connections in the database pool"
+            ),
+            &["state"],
+        )
+        .unwrap();
+        let http_request_seconds = HistogramVec::new(
+            histogram_opts!(
+                "query_api_http_request_seconds",
+                "Time to respond to a request"
+            ),
+            &["route", "status"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(update_seconds.clone())).unwrap();
+        registry
+            .register(Box::new(page_fetch_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(page_parse_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(pages_fetched.clone())).unwrap();
+        registry
+            .register(Box::new(upstream_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(auctions_inserted.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(auctions_skipped.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(webhook_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(database_connections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(http_request_seconds.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            update_seconds,
+            page_fetch_seconds,
+            page_parse_seconds,
+            pages_fetched,
+            upstream_failures,
+            auctions_inserted,
+            auctions_skipped,
+            webhook_failures,
+            database_connections,
+            http_request_seconds,
+        }
+    }
+
+    /// Encodes every metric in the Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records a page fetched from the source (auctions or auctions_ended) since started
+pub fn observe_fetch(source: &str, started: Instant, success: bool) {
+    METRICS
+        .page_fetch_seconds
+        .with_label_values(&[source])
+        .observe(started.elapsed().as_secs_f64());
+    if success {
+        METRICS.pages_fetched.with_label_values(&[source]).inc();
+    } else {
+        METRICS.upstream_failures.with_label_values(&[source]).inc();
+    }
+}
+
+pub fn observe_parse(source: &str, started: Instant) {
+    METRICS
+        .page_parse_seconds
+        .with_label_values(&[source])
+        .observe(started.elapsed().as_secs_f64());
+}
+
+/// Records the time to respond to a request. The route is an endpoint name from the routing table
+/// (or other), never the raw path, so the label can't have unbounded values
+pub fn observe_request(route: &'static str, status: u16, latency: Duration) {
+    METRICS
+        .http_request_seconds
+        .with_label_values(&[route, &status.to_string()])
+        .observe(latency.as_secs_f64());
+}
+
+/// Gathers the database pool status and encodes every metric
+pub async fn get_metrics() -> String {
+    if let Some(pool) = DATABASE.lock().await.as_ref() {
+        let status = pool.status();
+        for (state, connections) in [
+            ("max", status.max_size),
+            ("open", status.size),
+            ("idle", status.available),
+            ("waiting", status.waiting),
+        ] {
+            METRICS
+                .database_connections
+                .with_label_values(&[state])
+                .set(connections as i64);
+        }
+    }
+
+    METRICS.encode()
+}
@@ -0,0 +1,215 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Offline stand-in for the Hypixel auction endpoints. Point `HYPIXEL_API_URL` at it to run
+//! the auction loop without network access.
+
+use crate::utils::get_timestamp_millis;
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::{
+    body::{Body, Bytes},
+    header,
+    service::service_fn,
+    Error, Request, Response, StatusCode,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
+use log::info;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+};
+use tokio::net::TcpListener;
+
+pub struct MockConfig {
+    /// Directory holding `auctions/{page}.json` and `auctions_ended.json`
+    pub data_dir: PathBuf,
+    pub address: SocketAddr,
+    /// Value of the `age` header. Negative means derive it from the clock like Cloudflare does
+    pub age: AtomicI64,
+    /// Replace `lastUpdated` with the start of the current minute so every minute looks like a new API update
+    pub rewrite_last_updated: bool,
+}
+
+impl MockConfig {
+    pub fn load_or_panic() -> Self {
+        let data_dir = env::var("MOCK_DATA_DIR").unwrap_or_else(|_| String::from("mock_data"));
+        let address = env::var("MOCK_ADDRESS")
+            .unwrap_or_else(|_| String::from("127.0.0.1:8001"))
+            .parse::<SocketAddr>()
+            .expect("MOCK_ADDRESS not valid");
+        let age = env::var("MOCK_AGE")
+            .ok()
+            .map(|age| age.parse::<i64>().expect("MOCK_AGE not valid"))
+            .unwrap_or(-1);
+        let rewrite_last_updated = env::var("MOCK_REWRITE_LAST_UPDATED")
+            .unwrap_or_else(|_| String::from("true"))
+            .parse()
+            .unwrap_or(true);
+
+        MockConfig {
+            data_dir: PathBuf::from(data_dir),
+            address,
+            age: AtomicI64::new(age),
+            rewrite_last_updated,
+        }
+    }
+
+    fn current_age(&self) -> i64 {
+        let age = self.age.load(Ordering::Relaxed);
+        if age >= 0 {
+            age
+        } else {
+            ((get_timestamp_millis() / 1000) % 60) as i64
+        }
+    }
+}
+
+/// Starts the mock upstream listening on the configured address
+pub async fn start_mock_server(
+    config: Arc<MockConfig>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind(config.address).await?;
+
+    info!("Mock Hypixel API listening on http://{}", config.address);
+
+    loop {
+        let (tcp, _) = listener.accept().await?;
+        let io = TokioIo::new(tcp);
+        let captured_config = config.clone();
+
+        tokio::task::spawn(async move {
+            if let Err(err) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(
+                    io,
+                    service_fn(move |req| handle_mock_response(captured_config.clone(), req)),
+                )
+                .await
+            {
+                println!("Error serving connection: {:?}", err);
+            }
+        });
+    }
+}
+
+/* Handles http requests to the mock upstream */
+async fn handle_mock_response(
+    config: Arc<MockConfig>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    info!("{} {}", req.method(), req.uri());
+
+    let url = Url::parse(&format!("http://{}{}", config.address, req.uri())).unwrap();
+
+    match url.path() {
+        "/skyblock/auctions" => {
+            let page = url
+                .query_pairs()
+                .find(|query_pair| query_pair.0 == "page")
+                .and_then(|query_pair| query_pair.1.parse::<i32>().ok())
+                .unwrap_or(0);
+
+            recorded_response(
+                &config,
+                config
+                    .data_dir
+                    .join("auctions")
+                    .join(format!("{}.json", page)),
+            )
+        }
+        "/skyblock/auctions_ended" => {
+            recorded_response(&config, config.data_dir.join("auctions_ended.json"))
+        }
+        "/mock/age" => {
+            // Negative or missing values go back to deriving the age from the clock
+            let age = url
+                .query_pairs()
+                .find(|query_pair| query_pair.0 == "age")
+                .and_then(|query_pair| query_pair.1.parse::<i64>().ok())
+                .unwrap_or(-1);
+            config.age.store(age, Ordering::Relaxed);
+
+            mock_response(
+                StatusCode::OK,
+                config.current_age(),
+                &json!({"success": true, "age": age}),
+            )
+        }
+        _ => mock_response(
+            StatusCode::NOT_FOUND,
+            config.current_age(),
+            &json!({"success": false, "cause": "Not found"}),
+        ),
+    }
+}
+
+fn recorded_response(
+    config: &MockConfig,
+    path: PathBuf,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let age = config.current_age();
+
+    let mut json = match fs::read_to_string(&path)
+        .ok()
+        .and_then(|file| serde_json::from_str::<Value>(&file).ok())
+    {
+        Some(json) => json,
+        None => {
+            return mock_response(
+                StatusCode::NOT_FOUND,
+                age,
+                &json!({"success": false, "cause": format!("Unable to read {}", path.display())}),
+            )
+        }
+    };
+
+    if config.rewrite_last_updated {
+        if let Some(last_updated) = json.get_mut("lastUpdated") {
+            let now = get_timestamp_millis() as i64;
+            *last_updated = json!(now - now % 60000);
+        }
+    }
+
+    mock_response(StatusCode::OK, age, &json)
+}
+
+fn mock_response(
+    status: StatusCode,
+    age: i64,
+    json: &Value,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::AGE, age)
+        .body(
+            Full::new(serde_json::to_vec(json).unwrap().into())
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+        .unwrap())
+}
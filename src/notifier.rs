@@ -0,0 +1,332 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Destinations for the information and error logs of each update
+
+use crate::{
+    statics::NOTIFICATION_QUEUE,
+    utils::get_timestamp_secs,
+    webhook::{post_json, Webhook},
+};
+use futures::{future::BoxFuture, FutureExt};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{error::Error, fs::OpenOptions, io::Write, str::FromStr, time::Duration};
+use tokio::{
+    sync::mpsc,
+    task,
+    time::{timeout_at, Instant},
+};
+
+pub type NotifyResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Severity {
+    Info,
+    Error,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "INFO" => Self::Info,
+            "ERROR" => Self::Error,
+            _ => return Err(format!("Unknown severity {}", s)),
+        })
+    }
+}
+
+pub struct Notification {
+    pub severity: Severity,
+    pub title: String,
+    pub description: String,
+    /// If the notifier's mention target should be pinged
+    pub mention: bool,
+}
+
+/// Merges notifications with the same severity and title into one, keeping the order they were first sent in
+pub fn coalesce(notifications: Vec<Notification>) -> Vec<Notification> {
+    let mut coalesced: Vec<Notification> = Vec::new();
+    for notification in notifications {
+        match coalesced.iter_mut().find(|existing| {
+            existing.severity == notification.severity && existing.title == notification.title
+        }) {
+            Some(existing) => {
+                existing.description.push('\n');
+                existing.description.push_str(&notification.description);
+                existing.mention |= notification.mention;
+            }
+            None => coalesced.push(notification),
+        }
+    }
+    coalesced
+}
+
+/// Starts delivering queued notifications in the background. Notifications sent within
+/// the window of the first one are coalesced, so noisy failures become a single message
+pub fn start_notification_queue(notifiers: Vec<Box<dyn Notifier>>, window: Duration) {
+    if notifiers.is_empty() {
+        return;
+    }
+
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Notification>();
+    let _ = NOTIFICATION_QUEUE.lock().unwrap().insert(sender);
+
+    tokio::spawn(async move {
+        while let Some(first) = receiver.recv().await {
+            let mut notifications = vec![first];
+            let deadline = Instant::now() + window;
+            while let Ok(Some(notification)) = timeout_at(deadline, receiver.recv()).await {
+                notifications.push(notification);
+            }
+
+            for notification in coalesce(notifications) {
+                for notifier in &notifiers {
+                    if notification.severity >= notifier.min_severity() {
+                        if let Err(e) = notifier.notify(&notification).await {
+                            // Not sent to the notifiers since it would queue another notification
+                            error!("Error sending notification: {}", e);
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+pub trait Notifier: Send + Sync {
+    /// Notifications less severe than this are not sent
+    fn min_severity(&self) -> Severity;
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, NotifyResult>;
+}
+
+/// How a notifier is configured in the `NOTIFIERS` file
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    Discord {
+        url: String,
+        #[serde(default = "default_severity")]
+        severity: Severity,
+        /// User or role to ping, such as <@1234>
+        mention: Option<String>,
+    },
+    Slack {
+        url: String,
+        #[serde(default = "default_severity")]
+        severity: Severity,
+        /// User or group to ping, such as <@U1234> or <!channel>
+        mention: Option<String>,
+    },
+    Http {
+        url: String,
+        #[serde(default = "default_severity")]
+        severity: Severity,
+    },
+    File {
+        path: String,
+        #[serde(default = "default_severity")]
+        severity: Severity,
+    },
+    Stdout {
+        #[serde(default = "default_severity")]
+        severity: Severity,
+    },
+}
+
+fn default_severity() -> Severity {
+    Severity::Info
+}
+
+impl NotifierConfig {
+    pub fn build(&self) -> Box<dyn Notifier> {
+        match self {
+            Self::Discord {
+                url,
+                severity,
+                mention,
+            } => Box::new(DiscordNotifier {
+                webhook: Webhook::from_url(url),
+                severity: *severity,
+                mention: mention.clone(),
+            }),
+            Self::Slack {
+                url,
+                severity,
+                mention,
+            } => Box::new(SlackNotifier {
+                url: url.to_string(),
+                severity: *severity,
+                mention: mention.clone(),
+            }),
+            Self::Http { url, severity } => Box::new(HttpNotifier {
+                url: url.to_string(),
+                severity: *severity,
+            }),
+            Self::File { path, severity } => Box::new(FileNotifier {
+                path: Some(path.to_string()),
+                severity: *severity,
+            }),
+            Self::Stdout { severity } => Box::new(FileNotifier {
+                path: None,
+                severity: *severity,
+            }),
+        }
+    }
+}
+
+/* Sends an embed to a Discord webhook */
+pub struct DiscordNotifier {
+    webhook: Webhook,
+    severity: Severity,
+    mention: Option<String>,
+}
+
+impl Notifier for DiscordNotifier {
+    fn min_severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, NotifyResult> {
+        async move {
+            self.webhook
+                .send(|message| {
+                    if notification.mention {
+                        if let Some(mention) = &self.mention {
+                            message.mention(mention);
+                        }
+                    }
+                    message.embed(|embed| {
+                        embed
+                            .title(&notification.title)
+                            .color(match notification.severity {
+                                Severity::Info => 0x00FFFF,
+                                Severity::Error => 0xFF0000,
+                            })
+                            .description(&notification.description)
+                    })
+                })
+                .await
+        }
+        .boxed()
+    }
+}
+
+/* Sends a message to a Slack compatible incoming webhook */
+pub struct SlackNotifier {
+    url: String,
+    severity: Severity,
+    mention: Option<String>,
+}
+
+impl Notifier for SlackNotifier {
+    fn min_severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, NotifyResult> {
+        async move {
+            let mention = match &self.mention {
+                Some(mention) if notification.mention => format!("{} ", mention),
+                _ => String::new(),
+            };
+            post_json(
+                &self.url,
+                &json!({
+                    "text": format!("{}*{}*\n{}", mention, notification.title, notification.description)
+                }),
+            )
+            .await
+        }
+        .boxed()
+    }
+}
+
+/* Posts the notification as JSON to any URL */
+pub struct HttpNotifier {
+    url: String,
+    severity: Severity,
+}
+
+impl Notifier for HttpNotifier {
+    fn min_severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, NotifyResult> {
+        async move {
+            post_json(
+                &self.url,
+                &json!({
+                    "severity": notification.severity,
+                    "title": notification.title,
+                    "description": notification.description,
+                    "mention": notification.mention
+                }),
+            )
+            .await
+        }
+        .boxed()
+    }
+}
+
+/* Appends the notification to a file, or prints it if there is no path */
+pub struct FileNotifier {
+    path: Option<String>,
+    severity: Severity,
+}
+
+impl Notifier for FileNotifier {
+    fn min_severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, NotifyResult> {
+        async move {
+            let line = format!(
+                "[{}] {:?} {}: {}\n",
+                get_timestamp_secs(),
+                notification.severity,
+                notification.title,
+                notification.description.replace('\n', " | ")
+            );
+            match &self.path {
+                Some(path) => {
+                    let path = path.to_string();
+                    // Written on the blocking pool so a slow disk doesn't stall the runtime
+                    task::spawn_blocking(move || {
+                        OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(path)?
+                            .write_all(line.as_bytes())
+                    })
+                    .await??
+                }
+                None => print!("{}", line),
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
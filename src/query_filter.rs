@@ -0,0 +1,399 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! JSON filters for POST `/query`, resolved against the columns of the `query` table, and the SQL of
+//! the `min_` and `max_` query parameters
+
+use crate::structs::Bid;
+use postgres_types::ToSql;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio_postgres::Row;
+
+/// Body of a POST `/query` request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryRequest {
+    #[serde(default)]
+    pub filter: Option<Filter>,
+    #[serde(default)]
+    pub sort_by: Option<String>,
+    #[serde(default)]
+    pub sort_order: Option<String>,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    /// Empty for the first page, then the `next_cursor` of the previous page
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Only return these fields
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Leave out the lore and bids
+    #[serde(default)]
+    pub compact: bool,
+}
+
+fn default_limit() -> i64 {
+    1
+}
+
+/// A filter as it is sent, before its fields and values are checked
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    Eq {
+        field: String,
+        value: Value,
+    },
+    In {
+        field: String,
+        values: Vec<Value>,
+    },
+    /// Inclusive range, at least one of min or max is required
+    Range {
+        field: String,
+        #[serde(default)]
+        min: Option<Value>,
+        #[serde(default)]
+        max: Option<Value>,
+    },
+    /// Array column contains all of the values
+    Contains {
+        field: String,
+        values: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    Text,
+    BigInt,
+    SmallInt,
+    Real,
+    Bool,
+    TextArray,
+}
+
+/// Columns of the `query` table that can be filtered by
+const COLUMNS: [(&str, ColumnType); 36] = [
+    ("uuid", ColumnType::Text),
+    ("auctioneer", ColumnType::Text),
+    ("end_t", ColumnType::BigInt),
+    ("item_name", ColumnType::Text),
+    ("lore", ColumnType::Text),
+    ("tier", ColumnType::Text),
+    ("item_id", ColumnType::Text),
+    ("internal_id", ColumnType::Text),
+    ("starting_bid", ColumnType::BigInt),
+    ("highest_bid", ColumnType::BigInt),
+    ("lowestbin_price", ColumnType::Real),
+    ("enchants", ColumnType::TextArray),
+    ("attributes", ColumnType::TextArray),
+    ("bin", ColumnType::Bool),
+    ("count", ColumnType::SmallInt),
+    ("potato_books", ColumnType::SmallInt),
+    ("stars", ColumnType::SmallInt),
+    ("farming_for_dummies", ColumnType::SmallInt),
+    ("transmission_tuner", ColumnType::SmallInt),
+    ("mana_disintegrator", ColumnType::SmallInt),
+    ("reforge", ColumnType::Text),
+    ("rune", ColumnType::Text),
+    ("skin", ColumnType::Text),
+    ("power_scroll", ColumnType::Text),
+    ("drill_upgrade_module", ColumnType::Text),
+    ("drill_fuel_tank", ColumnType::Text),
+    ("drill_engine", ColumnType::Text),
+    ("dye", ColumnType::Text),
+    ("accessory_enrichment", ColumnType::Text),
+    ("recombobulated", ColumnType::Bool),
+    ("wood_singularity", ColumnType::Bool),
+    ("art_of_war", ColumnType::Bool),
+    ("art_of_peace", ColumnType::Bool),
+    ("etherwarp", ColumnType::Bool),
+    ("necron_scrolls", ColumnType::TextArray),
+    ("gemstones", ColumnType::TextArray),
+];
+
+/// Finds a column by name. The returned name is the one used in SQL, never the user's string
+pub fn get_column(field: &str) -> Option<(&'static str, ColumnType)> {
+    COLUMNS.iter().find(|(name, _)| *name == field).copied()
+}
+
+/// A value converted to the type of the column it is compared with
+#[derive(Debug, PartialEq)]
+pub enum FilterValue {
+    Text(String),
+    BigInt(i64),
+    SmallInt(i16),
+    Real(f32),
+    Bool(bool),
+}
+
+impl FilterValue {
+    fn new(field: &str, column_type: ColumnType, value: &Value) -> Result<FilterValue, String> {
+        let filter_value = match column_type {
+            ColumnType::Text => value.as_str().map(|v| FilterValue::Text(v.to_string())),
+            ColumnType::BigInt => value.as_i64().map(FilterValue::BigInt),
+            ColumnType::SmallInt => value
+                .as_i64()
+                .and_then(|v| i16::try_from(v).ok())
+                .map(FilterValue::SmallInt),
+            ColumnType::Real => value.as_f64().map(|v| FilterValue::Real(v as f32)),
+            ColumnType::Bool => value.as_bool().map(FilterValue::Bool),
+            ColumnType::TextArray => None,
+        };
+
+        filter_value.ok_or_else(|| format!("Invalid value for {}: {}", field, value))
+    }
+
+    pub fn as_sql(&self) -> &(dyn ToSql + Sync) {
+        match self {
+            FilterValue::Text(v) => v,
+            FilterValue::BigInt(v) => v,
+            FilterValue::SmallInt(v) => v,
+            FilterValue::Real(v) => v,
+            FilterValue::Bool(v) => v,
+        }
+    }
+}
+
+/// A filter whose fields are known columns and whose values match the column types
+#[derive(Debug, PartialEq)]
+pub enum Condition {
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+    Eq {
+        column: &'static str,
+        value: FilterValue,
+    },
+    In {
+        column: &'static str,
+        values: Vec<FilterValue>,
+    },
+    Range {
+        column: &'static str,
+        min: Option<FilterValue>,
+        max: Option<FilterValue>,
+    },
+    Contains {
+        column: &'static str,
+        values: Vec<String>,
+    },
+}
+
+impl Filter {
+    /// Checks the fields and values of every filter in the tree
+    pub fn resolve(self) -> Result<Condition, String> {
+        match self {
+            Filter::And(filters) => Ok(Condition::And(resolve_all(filters)?)),
+            Filter::Or(filters) => Ok(Condition::Or(resolve_all(filters)?)),
+            Filter::Not(filter) => Ok(Condition::Not(Box::new(filter.resolve()?))),
+            Filter::Eq { field, value } => {
+                let (column, column_type) = get_scalar_column(&field)?;
+                Ok(Condition::Eq {
+                    column,
+                    value: FilterValue::new(&field, column_type, &value)?,
+                })
+            }
+            Filter::In { field, values } => {
+                let (column, column_type) = get_scalar_column(&field)?;
+                Ok(Condition::In {
+                    column,
+                    values: values
+                        .iter()
+                        .map(|value| FilterValue::new(&field, column_type, value))
+                        .collect::<Result<Vec<FilterValue>, String>>()?,
+                })
+            }
+            Filter::Range { field, min, max } => {
+                let (column, column_type) = get_scalar_column(&field)?;
+                if matches!(column_type, ColumnType::Text | ColumnType::Bool) {
+                    return Err(format!("Range is not supported for {}", field));
+                }
+                if min.is_none() && max.is_none() {
+                    return Err(format!("Range for {} needs a min or max", field));
+                }
+
+                Ok(Condition::Range {
+                    column,
+                    min: min
+                        .map(|min| FilterValue::new(&field, column_type, &min))
+                        .transpose()?,
+                    max: max
+                        .map(|max| FilterValue::new(&field, column_type, &max))
+                        .transpose()?,
+                })
+            }
+            Filter::Contains { field, values } => match get_column(&field) {
+                Some((column, ColumnType::TextArray)) => Ok(Condition::Contains { column, values }),
+                Some(_) => Err(format!(
+                    "Contains is only supported for array fields, not {}",
+                    field
+                )),
+                None => Err(format!("Unknown field: {}", field)),
+            },
+        }
+    }
+}
+
+fn resolve_all(filters: Vec<Filter>) -> Result<Vec<Condition>, String> {
+    filters.into_iter().map(Filter::resolve).collect()
+}
+
+fn get_scalar_column(field: &str) -> Result<(&'static str, ColumnType), String> {
+    match get_column(field) {
+        Some((_, ColumnType::TextArray)) => {
+            Err(format!("Use contains to filter the array field {}", field))
+        }
+        Some(column) => Ok(column),
+        None => Err(format!("Unknown field: {}", field)),
+    }
+}
+
+/// Builds the select list of the requested fields, or none to select every column. Compact leaves
+/// out the lore and bids. Required columns are always selected
+pub fn get_select_columns(
+    fields: &[String],
+    compact: bool,
+    required: &[&str],
+) -> Result<Option<String>, String> {
+    if fields.is_empty() && !compact {
+        return Ok(None);
+    }
+
+    let mut columns: Vec<&str> = Vec::new();
+    if fields.is_empty() {
+        columns.extend(COLUMNS.iter().map(|(name, _)| *name));
+        columns.push("bids");
+    } else {
+        for field in fields {
+            let column = match field.trim() {
+                "bids" => "bids",
+                field => get_column(field)
+                    .map(|(name, _)| name)
+                    .ok_or_else(|| format!("Unknown field: {}", field))?,
+            };
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+    }
+
+    // Never serialized, so there is no point in selecting it
+    columns.retain(|column| *column != "lowestbin_price");
+    if compact {
+        columns.retain(|column| *column != "lore" && *column != "bids");
+    }
+    if columns.is_empty() {
+        return Err(String::from("No fields left to return"));
+    }
+    for column in required {
+        if !columns.contains(column) {
+            columns.push(column);
+        }
+    }
+
+    Ok(Some(columns.join(", ")))
+}
+
+/// Converts a row with some of the query table columns to JSON. Like `QueryDatabaseItem`, empty
+/// values are left out
+pub fn row_to_json(row: &Row) -> Map<String, Value> {
+    let mut map = Map::new();
+
+    for (idx, column) in row.columns().iter().enumerate() {
+        let name = column.name();
+        let value = match name {
+            "score" => json!(row.get::<_, Option<i32>>(idx)),
+            "bids" => json!(row.get::<_, Option<Vec<Bid>>>(idx)),
+            "lowestbin_price" => continue,
+            _ => match get_column(name) {
+                Some((_, ColumnType::Text)) => json!(row.get::<_, Option<String>>(idx)),
+                Some((_, ColumnType::BigInt)) => json!(row.get::<_, Option<i64>>(idx)),
+                Some((_, ColumnType::SmallInt)) => json!(row.get::<_, Option<i16>>(idx)),
+                Some((_, ColumnType::Real)) => json!(row.get::<_, Option<f32>>(idx)),
+                Some((_, ColumnType::Bool)) => json!(row.get::<_, Option<bool>>(idx)),
+                Some((_, ColumnType::TextArray)) => json!(row.get::<_, Option<Vec<String>>>(idx)),
+                // Columns added by the query itself, such as the bid of a bidder search
+                None => continue,
+            },
+        };
+
+        let is_empty = match &value {
+            Value::Null => true,
+            Value::Bool(value) => !value && name != "bin",
+            Value::Array(value) => value.is_empty(),
+            _ => false,
+        };
+        if !is_empty {
+            map.insert(name.to_string(), value);
+        }
+    }
+
+    map
+}
+
+/// Price of one item in an auction. The count is at least one so it never divides by zero
+pub const PRICE_PER_UNIT_SQL: &str =
+    "GREATEST(starting_bid, highest_bid)::DOUBLE PRECISION / GREATEST(count, 1)";
+
+/// Adds the conditions of the `min_` and `max_` parameters of `param_name` to the end of the
+/// WHERE clause (or to the conditions after the query score if sorting by query). Returns the next
+/// parameter number
+pub fn range_filter<'a, T: ToSql + Sync>(
+    sql: &mut String,
+    sort_by_query_end_sql: &mut String,
+    param_vec: &mut Vec<&'a (dyn ToSql + Sync)>,
+    param_name: &str,
+    min_value: &'a Option<T>,
+    max_value: &'a Option<T>,
+    param_count: i32,
+    sort_by_query: bool,
+) -> i32 {
+    let mut param_count_mut = param_count;
+
+    for (operator, param_value) in [(">=", min_value), ("<=", max_value)] {
+        if let Some(param_value) = param_value {
+            if sort_by_query {
+                if !sort_by_query_end_sql.is_empty() {
+                    sort_by_query_end_sql.push_str(" AND");
+                }
+                sort_by_query_end_sql.push_str(&format!(
+                    " {} {} ${}",
+                    param_name, operator, param_count_mut
+                ));
+            } else {
+                if param_count_mut != 1 {
+                    sql.push_str(" AND");
+                }
+                sql.push_str(&format!(
+                    " {} {} ${}",
+                    param_name, operator, param_count_mut
+                ));
+            }
+            param_vec.push(param_value);
+            param_count_mut += 1;
+        }
+    }
+
+    param_count_mut
+}
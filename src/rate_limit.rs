@@ -0,0 +1,309 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Token bucket rate limits and daily quotas per API key and per IP
+
+use crate::{statics::RATE_LIMIT_USAGE, utils::get_timestamp_millis};
+use serde::Deserialize;
+use std::{collections::HashMap, fs, time::Duration};
+use tokio::time::interval;
+
+const DAY_MILLIS: i64 = 86400000;
+/// Most clients tracked at once. The least recently used client is forgotten to track a new one
+pub const MAX_TRACKED: usize = 100000;
+/// How often clients whose usage is the same as a new client are forgotten
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Limits for each endpoint. Loaded from the JSON file at `RATE_LIMITS`
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    /// Used for endpoints that are not listed
+    pub default: EndpointLimits,
+    /// Limits by path, for example /average
+    pub endpoints: HashMap<String, EndpointLimits>,
+    /// Identify clients by the first address of the X-Forwarded-For header, only enable behind a proxy
+    pub trust_forwarded_for: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EndpointLimits {
+    /// Limits for each API key
+    pub key: Option<Limit>,
+    /// Limits for each IP, applied to every request including those with a key
+    pub ip: Option<Limit>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    /// Requests the bucket refills per minute
+    #[serde(default)]
+    pub per_minute: Option<f64>,
+    /// Most requests that can be made at once (defaults to per_minute)
+    #[serde(default)]
+    pub burst: Option<f64>,
+    /// Requests allowed per UTC day
+    #[serde(default)]
+    pub daily: Option<u64>,
+}
+
+impl Limit {
+    fn burst(&self) -> f64 {
+        self.burst.or(self.per_minute).unwrap_or(0.0).max(1.0)
+    }
+}
+
+impl RateLimits {
+    pub fn load_or_panic(path: &str) -> Self {
+        if path.is_empty() {
+            return Self::default();
+        }
+
+        let file = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Unable to read rate limits {}: {}", path, e));
+        serde_json::from_str(&file)
+            .unwrap_or_else(|e| panic!("Unable to parse rate limits {}: {}", path, e))
+    }
+
+    /// The limits of the endpoint and the name its usage is tracked under
+    pub fn get(&self, path: &str) -> (&str, &EndpointLimits) {
+        match self.endpoints.get_key_value(path) {
+            Some((path, limits)) => (path, limits),
+            None => ("*", &self.default),
+        }
+    }
+}
+
+/// How much of a limit is left after a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitStatus {
+    pub allowed: bool,
+    /// Seconds until the request can be retried, if it was not allowed
+    pub retry_after: u64,
+    /// Bucket size, tokens left, and seconds until the bucket is full
+    pub rate: Option<(u64, u64, u64)>,
+    /// Daily quota, requests left today, and seconds until the quota resets
+    pub quota: Option<(u64, u64, u64)>,
+}
+
+impl LimitStatus {
+    /// Combines the statuses of two limits that both apply, keeping whichever has less left
+    pub fn min(self, other: LimitStatus) -> LimitStatus {
+        fn min_remaining(
+            a: Option<(u64, u64, u64)>,
+            b: Option<(u64, u64, u64)>,
+        ) -> Option<(u64, u64, u64)> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(if b.1 < a.1 { b } else { a }),
+                (a, b) => a.or(b),
+            }
+        }
+
+        LimitStatus {
+            allowed: self.allowed && other.allowed,
+            retry_after: self.retry_after.max(other.retry_after),
+            rate: min_remaining(self.rate, other.rate),
+            quota: min_remaining(self.quota, other.quota),
+        }
+    }
+}
+
+/// Token bucket and daily quota usage of one client on one endpoint
+#[derive(Debug, Clone)]
+pub struct Usage {
+    tokens: f64,
+    /// Epoch milliseconds the tokens were last refilled
+    updated: i64,
+    /// Days since the epoch of requests_today
+    day: i64,
+    requests_today: u64,
+    /// Epoch milliseconds the usage is the same as a new client (full bucket and unused quota)
+    idle_at: i64,
+}
+
+impl Usage {
+    pub fn new(limit: &Limit, now: i64) -> Self {
+        Self {
+            tokens: limit.burst(),
+            updated: now,
+            day: now / DAY_MILLIS,
+            requests_today: 0,
+            idle_at: now,
+        }
+    }
+
+    /// Takes a token and counts the request towards the quota if both have room
+    pub fn take(&mut self, limit: &Limit, now: i64) -> LimitStatus {
+        let day = now / DAY_MILLIS;
+        if day != self.day {
+            self.day = day;
+            self.requests_today = 0;
+        }
+        let seconds_until_tomorrow = ((day + 1) * DAY_MILLIS - now + 999) as u64 / 1000;
+
+        let burst = limit.burst();
+        if let Some(per_minute) = limit.per_minute {
+            let elapsed = (now - self.updated).max(0) as f64 / 1000.0;
+            self.tokens = (self.tokens + elapsed * per_minute / 60.0).min(burst);
+        }
+        self.updated = now;
+
+        let has_quota = limit.daily.is_none_or(|daily| self.requests_today < daily);
+        let has_token = limit.per_minute.is_none() || self.tokens >= 1.0;
+        let allowed = has_quota && has_token;
+        if allowed {
+            if limit.per_minute.is_some() {
+                self.tokens -= 1.0;
+            }
+            self.requests_today += 1;
+        }
+
+        let refilled_at = match limit.per_minute {
+            Some(per_minute) if per_minute > 0.0 => {
+                now + ((burst - self.tokens) * 60000.0 / per_minute).ceil() as i64
+            }
+            // The bucket never refills, so it is only forgotten after a day without requests
+            Some(_) => now + DAY_MILLIS,
+            None => now,
+        };
+        self.idle_at = match limit.daily {
+            Some(_) => refilled_at.max((day + 1) * DAY_MILLIS),
+            None => refilled_at,
+        };
+
+        let seconds_until = |tokens: f64| match limit.per_minute {
+            Some(per_minute) if per_minute > 0.0 => {
+                (tokens.max(0.0) * 60.0 / per_minute).ceil() as u64
+            }
+            _ => seconds_until_tomorrow,
+        };
+
+        LimitStatus {
+            allowed,
+            retry_after: if !has_quota {
+                seconds_until_tomorrow
+            } else if !has_token {
+                seconds_until(1.0 - self.tokens).max(1)
+            } else {
+                0
+            },
+            rate: limit.per_minute.map(|_| {
+                (
+                    burst as u64,
+                    self.tokens.floor() as u64,
+                    seconds_until(burst - self.tokens),
+                )
+            }),
+            quota: limit.daily.map(|daily| {
+                (
+                    daily,
+                    daily.saturating_sub(self.requests_today),
+                    seconds_until_tomorrow,
+                )
+            }),
+        }
+    }
+
+    /// If the bucket has refilled and the daily quota has reset, so the usage is the same as a new client
+    pub fn is_idle(&self, now: i64) -> bool {
+        now >= self.idle_at
+    }
+
+    /// Gives back a request that another limit did not allow
+    pub fn refund(&mut self, limit: &Limit) {
+        if limit.per_minute.is_some() {
+            self.tokens = (self.tokens + 1.0).min(limit.burst());
+        }
+        self.requests_today = self.requests_today.saturating_sub(1);
+    }
+}
+
+/// Checks the IP limit and, if the request has a known key, the key limit of the endpoint.
+/// Unknown keys are only limited by IP, so random keys can't each be given a new bucket.
+/// Returns none if the endpoint has no limits
+pub fn check_rate_limits(
+    rate_limits: &RateLimits,
+    path: &str,
+    ip: &str,
+    key: Option<&str>,
+    now: i64,
+) -> Option<LimitStatus> {
+    let (endpoint, limits) = rate_limits.get(path);
+
+    let ip_id = format!("ip:{}:{}", endpoint, ip);
+    let ip_status = limits.ip.as_ref().map(|limit| take(&ip_id, limit, now));
+    if ip_status.is_some_and(|status| !status.allowed) {
+        return ip_status;
+    }
+
+    let key_status = match (&limits.key, key) {
+        (Some(limit), Some(key)) => {
+            let status = take(&format!("key:{}:{}", endpoint, key), limit, now);
+            if !status.allowed {
+                if let (Some(ip_limit), Some(mut usage)) =
+                    (&limits.ip, RATE_LIMIT_USAGE.get_mut(&ip_id))
+                {
+                    usage.refund(ip_limit);
+                }
+            }
+            Some(status)
+        }
+        _ => None,
+    };
+
+    match (ip_status, key_status) {
+        (Some(ip_status), Some(key_status)) => Some(ip_status.min(key_status)),
+        (ip_status, key_status) => ip_status.or(key_status),
+    }
+}
+
+fn take(id: &str, limit: &Limit, now: i64) -> LimitStatus {
+    if RATE_LIMIT_USAGE.len() >= MAX_TRACKED && !RATE_LIMIT_USAGE.contains_key(id) {
+        // Make room by forgetting the client that made a request the longest time ago
+        let least_recent = RATE_LIMIT_USAGE
+            .iter()
+            .min_by_key(|usage| usage.updated)
+            .map(|usage| usage.key().clone());
+        if let Some(least_recent) = least_recent {
+            RATE_LIMIT_USAGE.remove(&least_recent);
+        }
+    }
+
+    RATE_LIMIT_USAGE
+        .entry(id.to_string())
+        .or_insert_with(|| Usage::new(limit, now))
+        .take(limit, now)
+}
+
+/// Forgets idle clients every minute, so requests never have to wait for the whole map to be checked
+pub fn start_rate_limit_pruner() {
+    tokio::spawn(async {
+        let mut prune = interval(PRUNE_INTERVAL);
+        loop {
+            prune.tick().await;
+            prune_rate_limits(get_timestamp_millis() as i64);
+        }
+    });
+}
+
+/// Forgets the clients whose usage is the same as a new client
+pub fn prune_rate_limits(now: i64) {
+    RATE_LIMIT_USAGE.retain(|_, usage| !usage.is_idle(now));
+}
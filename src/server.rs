@@ -17,16 +17,28 @@
  */
 
 use crate::{
+    alerts::{check_webhook_url, AlertRule},
+    api_keys::{create_api_key, list_api_keys, revoke_api_key, Scope},
     config::{Config, Feature},
+    cursor::{QueryCursor, QueryPage},
+    error::RawQueryError,
+    metrics::{get_metrics, observe_request},
+    query_filter::{
+        get_select_columns, range_filter, row_to_json, Condition, Filter, QueryRequest,
+        PRICE_PER_UNIT_SQL,
+    },
+    rate_limit::{check_rate_limits, LimitStatus},
     statics::*,
+    stream::{subscribe, StreamFilter},
     structs::*,
+    usage::{get_key_name, get_usage_report, record_usage, UsageRecord},
     utils::*,
 };
 use dashmap::DashMap;
 use futures::TryStreamExt;
-use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use http_body_util::{combinators::BoxBody, BodyExt, Full, Limited, StreamBody};
 use hyper::{
-    body::{Body, Bytes},
+    body::{Body, Bytes, Frame, Incoming},
     header,
     service::service_fn,
     Error, Method, Request, Response, StatusCode,
@@ -39,9 +51,14 @@ use log::info;
 use postgres_types::ToSql;
 use reqwest::Url;
 use serde::Serialize;
-use serde_json::json;
-use std::{fs, net::SocketAddr, sync::Arc};
-use tokio::net::TcpListener;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpListener, sync::broadcast::error::RecvError};
 use tokio_postgres::Row;
 
 /// Starts the server listening on URL
@@ -54,7 +71,7 @@ pub async fn start_server(
     info(format!("Listening on http://{}", address));
 
     loop {
-        let (tcp, _) = listener.accept().await?;
+        let (tcp, remote_address) = listener.accept().await?;
         let io = TokioIo::new(tcp);
         let captured_config = config.clone();
 
@@ -62,7 +79,9 @@ pub async fn start_server(
             if let Err(err) = auto::Builder::new(TokioExecutor::new())
                 .serve_connection(
                     io,
-                    service_fn(move |req| handle_response(captured_config.clone(), req)),
+                    service_fn(move |req| {
+                        handle_response(captured_config.clone(), remote_address, req)
+                    }),
                 )
                 .await
             {
@@ -72,69 +91,264 @@ pub async fn start_server(
     }
 }
 
-/* Handles http requests to the server */
+/* Largest accepted POST /query body */
+const QUERY_BODY_LIMIT: usize = 64 * 1024;
+
+/* How often the key of a stream subscriber is checked again */
+const STREAM_KEY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/* Handlers that requests are routed to */
+#[derive(Clone, Copy, PartialEq)]
+enum Handler {
+    Base,
+    Query,
+    QueryItems,
+    Pets,
+    Lowestbin,
+    LowestbinHistory,
+    Underbin,
+    AverageAuction,
+    AverageBin,
+    Average,
+    History,
+    Alerts,
+    Keys,
+    Usage,
+    Metrics,
+    Stream,
+    Debug,
+    Info,
+}
+
+/* Each path that is routed to a handler. The path also names the endpoint in the usage, metrics,
+and rate limits */
+const ROUTES: [(&str, Handler); 22] = [
+    ("/", Handler::Base),
+    ("/query", Handler::Query),
+    ("/query_items", Handler::QueryItems),
+    ("/pets", Handler::Pets),
+    ("/lowestbin", Handler::Lowestbin),
+    ("/lowestbin/history", Handler::LowestbinHistory),
+    ("/underbin", Handler::Underbin),
+    ("/average_auction", Handler::AverageAuction),
+    ("/average_bin", Handler::AverageBin),
+    ("/average", Handler::Average),
+    ("/history", Handler::History),
+    ("/alerts", Handler::Alerts),
+    ("/alerts/create", Handler::Alerts),
+    ("/alerts/delete", Handler::Alerts),
+    ("/keys", Handler::Keys),
+    ("/keys/create", Handler::Keys),
+    ("/keys/revoke", Handler::Keys),
+    ("/usage", Handler::Usage),
+    ("/metrics", Handler::Metrics),
+    ("/stream", Handler::Stream),
+    ("/debug", Handler::Debug),
+    ("/info", Handler::Info),
+];
+
+/* Endpoint name and handler a path is routed to. Unknown paths are "other" so they cannot flood the
+usage table or metrics */
+fn get_route(path: &str) -> (&'static str, Option<Handler>) {
+    ROUTES
+        .into_iter()
+        .find(|(endpoint, _)| *endpoint == path)
+        .map_or(("other", None), |(endpoint, handler)| {
+            (endpoint, Some(handler))
+        })
+}
+
+/* Handles http requests to the server, recording the usage of each request */
 async fn handle_response(
     config: Arc<Config>,
-    req: Request<impl Body>,
+    remote_address: SocketAddr,
+    req: Request<Incoming>,
 ) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
     info!("{} {}", req.method(), req.uri().path());
 
+    let start = Instant::now();
+    let time = get_timestamp_millis() as i64;
+    let (endpoint, handler) = get_route(req.uri().path());
+    let key = Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+        .find(|query_pair| query_pair.0 == "key")
+        .map(|query_pair| query_pair.1.to_string())
+        .unwrap_or_default();
+
+    let response = rate_limit(config.clone(), remote_address, &key, endpoint, handler, req).await?;
+
+    let latency = start.elapsed();
+    observe_request(endpoint, response.status().as_u16(), latency);
+    record_usage(UsageRecord {
+        time,
+        key: get_key_name(&config, &key),
+        endpoint: endpoint.to_string(),
+        status: response.status().as_u16(),
+        latency_ms: latency.as_secs_f64() * 1000.0,
+        // Streamed responses have no known size
+        response_bytes: response.body().size_hint().exact().unwrap_or(0),
+    });
+
+    Ok(response)
+}
+
+/* Applies the rate limits of the endpoint before routing the request */
+async fn rate_limit(
+    config: Arc<Config>,
+    remote_address: SocketAddr,
+    key: &str,
+    endpoint: &str,
+    handler: Option<Handler>,
+    req: Request<Incoming>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut ip = remote_address.ip().to_string();
+    if config.rate_limits.trust_forwarded_for {
+        if let Some(forwarded_for) = req
+            .headers()
+            .get("X-Forwarded-For")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.split(',').next())
+            .map(|forwarded_for| forwarded_for.trim())
+            .filter(|forwarded_for| !forwarded_for.is_empty())
+        {
+            ip = forwarded_for.to_string();
+        }
+    }
+    // Unknown keys are only limited by IP
+    let has_key_limit = config.rate_limits.get(endpoint).1.key.is_some();
+    let known_key = if has_key_limit && is_known_api_key(&config, key).await {
+        Some(key)
+    } else {
+        None
+    };
+    let Some(status) = check_rate_limits(
+        &config.rate_limits,
+        endpoint,
+        &ip,
+        known_key,
+        get_timestamp_millis() as i64,
+    ) else {
+        return route(config, handler, req).await;
+    };
+
+    let mut response = if status.allowed {
+        route(config, handler, req).await?
+    } else {
+        let mut response = http_err(
+            StatusCode::TOO_MANY_REQUESTS,
+            if status.quota.is_some_and(|quota| quota.1 == 0) {
+                "Daily quota exceeded"
+            } else {
+                "Rate limit exceeded"
+            },
+        )?;
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            header::HeaderValue::from(status.retry_after),
+        );
+        response
+    };
+    add_rate_limit_headers(&mut response, &status);
+
+    Ok(response)
+}
+
+fn add_rate_limit_headers(response: &mut Response<BoxBody<Bytes, Error>>, status: &LimitStatus) {
+    let headers = response.headers_mut();
+    if let Some((limit, remaining, reset)) = status.rate {
+        headers.insert("X-RateLimit-Limit", limit.into());
+        headers.insert("X-RateLimit-Remaining", remaining.into());
+        headers.insert("X-RateLimit-Reset", reset.into());
+    }
+    if let Some((limit, remaining, reset)) = status.quota {
+        headers.insert("X-Quota-Limit", limit.into());
+        headers.insert("X-Quota-Remaining", remaining.into());
+        headers.insert("X-Quota-Reset", reset.into());
+    }
+}
+
+/* Routes http requests to their handler */
+async fn route(
+    config: Arc<Config>,
+    handler: Option<Handler>,
+    req: Request<Incoming>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    if req.method() == Method::POST && handler == Some(Handler::Query) {
+        return if config.is_enabled(Feature::Query) {
+            query_filter(config, req).await
+        } else {
+            bad_request("Query feature is not enabled")
+        };
+    }
+
     if req.method() != Method::GET {
         return not_implemented();
     }
 
-    match req.uri().path() {
-        "/" => base(config).await,
-        "/query" => {
+    let Some(handler) = handler else {
+        return not_found();
+    };
+
+    match handler {
+        Handler::Base => base(config).await,
+        Handler::Query => {
             if config.is_enabled(Feature::Query) {
                 query(config, req).await
             } else {
                 bad_request("Query feature is not enabled")
             }
         }
-        "/query_items" => {
+        Handler::QueryItems => {
             if config.is_enabled(Feature::Query) {
                 query_items(config, req).await
             } else {
                 bad_request("Query feature is not enabled")
             }
         }
-        "/pets" => {
+        Handler::Pets => {
             if config.is_enabled(Feature::Pets) {
                 pets(config, req).await
             } else {
                 bad_request("Pets feature is not enabled")
             }
         }
-        "/lowestbin" => {
+        Handler::Lowestbin => {
             if config.is_enabled(Feature::Lowestbin) {
                 lowestbin(config, req).await
             } else {
                 bad_request("Lowest bins feature is not enabled")
             }
         }
-        "/underbin" => {
+        Handler::LowestbinHistory => {
+            if config.is_enabled(Feature::Lowestbin) {
+                lowestbin_history(config, req).await
+            } else {
+                bad_request("Lowest bins feature is not enabled")
+            }
+        }
+        Handler::Underbin => {
             if config.is_enabled(Feature::Underbin) {
                 underbin(config, req).await
             } else {
                 bad_request("Under bins feature is not enabled")
             }
         }
-        "/average_auction" => {
+        Handler::AverageAuction => {
             if config.is_enabled(Feature::AverageAuction) {
                 averages(config, req, vec!["average_auction"]).await
             } else {
                 bad_request("Average auction feature is not enabled")
             }
         }
-        "/average_bin" => {
+        Handler::AverageBin => {
             if config.is_enabled(Feature::AverageBin) {
                 averages(config, req, vec!["average_bin"]).await
             } else {
                 bad_request("Average bin feature is not enabled")
             }
         }
-        "/average" => {
+        Handler::Average => {
             if config.is_enabled(Feature::AverageAuction) && config.is_enabled(Feature::AverageBin)
             {
                 averages(config, req, vec!["average_bin", "average_auction"]).await
@@ -142,21 +356,50 @@ async fn handle_response(
                 bad_request("Both average auction and average bin feature are not enabled")
             }
         }
-        "/debug" => {
+        Handler::History => history(config, req).await,
+        Handler::Alerts => {
+            if config.is_enabled(Feature::Alerts) {
+                alerts(config, req).await
+            } else {
+                bad_request("Alerts feature is not enabled")
+            }
+        }
+        Handler::Keys => {
+            if DATABASE.lock().await.is_some() {
+                keys(config, req).await
+            } else {
+                bad_request("API keys need the query, pets, or average features to be enabled")
+            }
+        }
+        Handler::Usage => {
+            if DATABASE.lock().await.is_some() {
+                usage(config, req).await
+            } else {
+                bad_request("Usage needs the query, pets, or average features to be enabled")
+            }
+        }
+        Handler::Metrics => metrics(config, req).await,
+        Handler::Stream => {
+            if config.disable_updating {
+                bad_request("Streaming is not available when updating is disabled")
+            } else {
+                stream(config, req).await
+            }
+        }
+        Handler::Debug => {
             if config.debug {
                 debug_log(config, req).await
             } else {
                 bad_request("Debug is not enabled")
             }
         }
-        "/info" => {
+        Handler::Info => {
             if config.debug {
                 info_log(config, req).await
             } else {
                 bad_request("Debug is not enabled")
             }
         }
-        _ => not_found(),
     }
 }
 
@@ -180,7 +423,7 @@ async fn debug_log(
         }
     }
 
-    if !valid_api_key(config, key, true) {
+    if !valid_api_key(config, key, Scope::DebugLogs).await {
         return unauthorized();
     }
 
@@ -215,7 +458,7 @@ async fn info_log(
         }
     }
 
-    if !valid_api_key(config, key, true) {
+    if !valid_api_key(config, key, Scope::DebugLogs).await {
         return unauthorized();
     }
 
@@ -254,7 +497,7 @@ async fn pets(
     }
 
     // The API key in request doesn't match
-    if !valid_api_key(config, key, false) {
+    if !valid_api_key(config, key, Scope::Pets).await {
         return unauthorized();
     }
 
@@ -264,16 +507,13 @@ async fn pets(
 
     let mut sql: String = String::from("SELECT * FROM pets WHERE name IN (");
     let mut param_vec: Vec<Box<String>> = Vec::new();
-    let mut param_count = 1;
 
-    let mut split = query.split(',');
-    for pet_name in split.by_ref() {
+    for (param_count, pet_name) in (1..).zip(query.split(',')) {
         if param_count != 1 {
             sql.push(',');
         }
         sql.push_str(&format!("${}", param_count));
         param_vec.push(Box::new(pet_name.to_string()));
-        param_count += 1;
     }
     sql.push(')');
 
@@ -317,6 +557,9 @@ async fn averages(
     let mut step = 60;
     let mut center = String::from("mean");
     let mut percent = 0.25;
+    let mut threshold = None;
+    let mut item_id = String::new();
+    let mut prefix = String::new();
 
     // Reads the query parameters from the request and stores them in the corresponding variable
     for query_pair in Url::parse(&format!(
@@ -338,16 +581,22 @@ async fn averages(
             },
             "key" => key = query_pair.1.to_string(),
             "center" => center = query_pair.1.to_string(),
+            "item_id" => item_id = query_pair.1.to_string(),
+            "prefix" => prefix = query_pair.1.to_string(),
             "percent" => match query_pair.1.to_string().parse::<f32>() {
                 Ok(percent_float) => percent = percent_float,
                 Err(e) => return bad_request(&format!("Error parsing percent parameter: {}", e)),
             },
+            "threshold" => match query_pair.1.to_string().parse::<f32>() {
+                Ok(threshold_float) => threshold = Some(threshold_float),
+                Err(e) => return bad_request(&format!("Error parsing threshold parameter: {}", e)),
+            },
             _ => {}
         }
     }
 
     // The API key in request doesn't match
-    if !valid_api_key(config, key, false) {
+    if !valid_api_key(config, key, Scope::Averages).await {
         return unauthorized();
     }
 
@@ -363,6 +612,57 @@ async fn averages(
         return bad_request("The percent parameter must be between 0 and 1");
     }
 
+    if center == "trimmed_mean" && percent >= 0.5 {
+        return bad_request("The percent parameter must be less than 0.5 when using trimmed_mean");
+    }
+
+    if threshold.is_some_and(|threshold| threshold <= 0.0) {
+        return bad_request("The threshold parameter must be positive");
+    }
+
+    // Percentile centers are formatted as p followed by a number from 0 to 100 (e.g. p25)
+    let mut percentile_rank = None;
+    if let Some(rank) = center.strip_prefix('p') {
+        match rank.parse::<f32>() {
+            Ok(rank_float) if (0.0..=100.0).contains(&rank_float) => {
+                percentile_rank = Some(rank_float)
+            }
+            _ => return bad_request("Percentile centers must be between p0 and p100"),
+        }
+    }
+
+    let mut filter_sql = String::new();
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = vec![&time];
+
+    // Only aggregate the requested items (either exact ids or ids starting with a prefix)
+    let item_ids: Vec<String>;
+    let prefix_patterns: Vec<String>;
+    let mut filters = Vec::new();
+    if !item_id.is_empty() {
+        item_ids = item_id.split(',').map(|s| s.trim().to_string()).collect();
+        param_vec.push(&item_ids);
+        filters.push(format!("item_id = ANY(${})", param_vec.len()));
+    }
+    if !prefix.is_empty() {
+        prefix_patterns = prefix
+            .split(',')
+            .map(|s| {
+                format!(
+                    "{}%",
+                    s.trim()
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                )
+            })
+            .collect();
+        param_vec.push(&prefix_patterns);
+        filters.push(format!("item_id LIKE ANY(${})", param_vec.len()));
+    }
+    if !filters.is_empty() {
+        filter_sql = format!(" AND ({})", filters.join(" OR "));
+    }
+
     // Map each item id to its prices and sales
     let avg_map: DashMap<String, AverageDatabaseItem> = DashMap::new();
 
@@ -371,8 +671,8 @@ async fn averages(
         let results_cursor = get_client()
             .await
             .query(
-                &format!("SELECT item_id, ARRAY_AGG((price, sales)::avg_ah) prices FROM {table} WHERE time_t > $1 GROUP BY item_id"),
-                &[&time],
+                &format!("SELECT item_id, ARRAY_AGG((price, sales)::avg_ah) prices FROM {table} WHERE time_t > $1{filter_sql} GROUP BY item_id"),
+                &param_vec,
             )
             .await;
 
@@ -402,7 +702,14 @@ async fn averages(
                 price: match center.as_str() {
                     "median" => ele.1.get_median(),
                     "modified_median" => ele.1.get_modified_median(percent),
-                    _ => ele.1.get_average(),
+                    "weighted_mean" => ele.1.get_weighted_average(),
+                    "trimmed_mean" => ele.1.get_trimmed_mean(percent),
+                    "iqr" => ele.1.get_iqr_mean(threshold.unwrap_or(1.5)),
+                    "mad" => ele.1.get_mad_mean(threshold.unwrap_or(3.0)),
+                    _ => match percentile_rank {
+                        Some(rank) => ele.1.get_percentile(rank),
+                        None => ele.1.get_average(),
+                    },
                 },
                 sales: ele.1.get_sales(count),
             },
@@ -417,6 +724,26 @@ async fn averages(
         .unwrap())
 }
 
+/* Parses a min_ or max_ query parameter into the variable with the same name, returning a bad
+request if it is invalid. Other parameters are ignored */
+macro_rules! parse_range_params {
+    ($query_pair:expr, $($param:ident),+ $(,)?) => {
+        match $query_pair.0.as_ref() {
+            $(stringify!($param) => match $query_pair.1.parse() {
+                Ok(value) => $param = Some(value),
+                Err(e) => {
+                    return bad_request(&format!(
+                        "Error parsing {} parameter: {}",
+                        stringify!($param),
+                        e
+                    ))
+                }
+            },)+
+            _ => {}
+        }
+    };
+}
+
 async fn query(
     config: Arc<Config>,
     req: Request<impl Body>,
@@ -456,6 +783,23 @@ async fn query(
     let mut etherwarp = Option::None;
     let mut necron_scrolls = String::new();
     let mut gemstones = String::new();
+    let mut min_starting_bid: Option<i64> = None;
+    let mut max_starting_bid: Option<i64> = None;
+    let mut min_highest_bid: Option<i64> = None;
+    let mut max_highest_bid: Option<i64> = None;
+    let mut min_stars: Option<i16> = None;
+    let mut max_stars: Option<i16> = None;
+    let mut min_potato_books: Option<i16> = None;
+    let mut max_potato_books: Option<i16> = None;
+    let mut min_count: Option<i16> = None;
+    let mut max_count: Option<i16> = None;
+    let mut min_end: Option<i64> = None;
+    let mut max_end: Option<i64> = None;
+    let mut min_price_per_unit: Option<f64> = None;
+    let mut max_price_per_unit: Option<f64> = None;
+    let mut cursor = Option::None;
+    let mut fields = String::new();
+    let mut compact = false;
 
     // Reads the query parameters from the request and stores them in the corresponding variable
     for query_pair in Url::parse(&format!(
@@ -566,381 +910,753 @@ async fn query(
             },
             "necron_scrolls" => necron_scrolls = query_pair.1.to_string(),
             "gemstones" => gemstones = query_pair.1.to_string(),
-            _ => {}
+            "cursor" => cursor = Some(query_pair.1.to_string()),
+            "fields" => fields = query_pair.1.to_string(),
+            "compact" => match query_pair.1.to_string().parse::<bool>() {
+                Ok(compact_bool) => compact = compact_bool,
+                Err(e) => return bad_request(&format!("Error parsing compact parameter: {}", e)),
+            },
+            _ => parse_range_params!(
+                query_pair,
+                min_starting_bid,
+                max_starting_bid,
+                min_highest_bid,
+                max_highest_bid,
+                min_stars,
+                max_stars,
+                min_potato_books,
+                max_potato_books,
+                min_count,
+                max_count,
+                min_end,
+                max_end,
+                min_price_per_unit,
+                max_price_per_unit,
+            ),
         }
     }
 
-    if !valid_api_key(config.clone(), key.to_owned(), false) {
+    if !valid_api_key(config.clone(), key.to_owned(), Scope::Query).await {
         return unauthorized();
     }
     // Prevent fetching too many rows
-    if (limit <= 0 || limit >= 500) && !valid_api_key(config.clone(), key.to_owned(), true) {
+    if (limit <= 0 || limit >= 500)
+        && !valid_api_key(config.clone(), key.to_owned(), Scope::RawSql).await
+    {
         return unauthorized();
     }
 
-    let database_ref = get_client().await;
-    let results_cursor;
-
-    // Find and sort using query
-    if query.is_empty() {
-        let mut sql = String::new();
-        let mut param_vec: Vec<&(dyn ToSql + Sync)> = Vec::new();
-        let mut param_count = 1;
-
-        let sort_by_query = sort_by == "query";
-        let mut sort_by_query_end_sql = String::new();
-
-        if !sort_by_query {
-            if !bids.is_empty() {
-                // TODO: support bids in sort_by query
-                sql =
-                    String::from("SELECT * FROM query, unnest(bids) AS bid WHERE bid.bidder = $1");
-                param_vec.push(&bids);
-                param_count += 1;
-            } else {
-                sql = String::from("SELECT * FROM query WHERE");
+    // Pages are sorted by the sort column (or uuid if not sorted) and then by uuid
+    let paginate = cursor.is_some();
+    let (page_sort_by, descending) = if (sort_by == "starting_bid" || sort_by == "highest_bid")
+        && (sort_order == "ASC" || sort_order == "DESC")
+    {
+        (sort_by.as_str(), sort_order == "DESC")
+    } else {
+        ("uuid", false)
+    };
+    let mut page_cursor = Option::None;
+    if let Some(cursor) = &cursor {
+        if !query.is_empty() || sort_by == "query" {
+            return bad_request(
+                "The cursor parameter cannot be used with the query parameter or when sorting by query",
+            );
+        }
+        if limit <= 0 {
+            return bad_request("The limit parameter must be positive when using a cursor");
+        }
+        if !cursor.is_empty() {
+            match QueryCursor::decode(cursor, page_sort_by, descending) {
+                Ok(cursor) => page_cursor = Some(cursor),
+                Err(e) => return bad_request(&e),
             }
         }
+    }
+    // Fetch one extra auction to know if there is another page
+    let page_limit = limit + 1;
 
-        param_count = int_eq(
-            &mut sql,
-            &mut param_vec,
-            "stars",
-            &stars,
-            param_count,
-            sort_by_query,
-        );
-        param_count = int_eq(
-            &mut sql,
-            &mut param_vec,
-            "potato_books",
-            &potato_books,
-            param_count,
-            sort_by_query,
-        );
-        param_count = int_eq(
-            &mut sql,
-            &mut param_vec,
-            "farming_for_dummies",
-            &farming_for_dummies,
-            param_count,
-            sort_by_query,
-        );
-        param_count = int_eq(
-            &mut sql,
-            &mut param_vec,
-            "transmission_tuner",
-            &transmission_tuner,
-            param_count,
-            sort_by_query,
-        );
-        param_count = int_eq(
-            &mut sql,
-            &mut param_vec,
-            "mana_disintegrator",
-            &mana_disintegrator,
-            param_count,
-            sort_by_query,
-        );
+    let fields_split = if fields.is_empty() {
+        Vec::new()
+    } else {
+        fields.split(',').map(|s| s.trim().to_string()).collect()
+    };
+    // Cursors are created from the uuid and sort column
+    let required_columns = if paginate {
+        vec!["uuid", page_sort_by]
+    } else {
+        Vec::new()
+    };
+    let select_columns = match get_select_columns(&fields_split, compact, &required_columns) {
+        Ok(select_columns) => select_columns,
+        Err(e) => return bad_request(&e),
+    };
+    let columns = select_columns.as_deref().unwrap_or("*");
+
+    // Run the admin raw SQL query on its own
+    if !query.is_empty() {
+        if !valid_api_key(config.clone(), key, Scope::RawSql).await {
+            return unauthorized();
+        }
 
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "reforge",
-            &reforge,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "rune",
-            &rune,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "skin",
-            &skin,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "tier",
-            &tier,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "dye",
-            &dye,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "internal_id",
-            &internal_id,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "power_scroll",
-            &power_scroll,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "drill_upgrade_module",
-            &drill_upgrade_module,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "drill_fuel_tank",
-            &drill_fuel_tank,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "drill_engine",
-            &drill_engine,
-            param_count,
-            sort_by_query,
-        );
-        param_count = str_eq(
-            &mut sql,
-            &mut param_vec,
-            "accessory_enrichment",
-            &accessory_enrichment,
-            param_count,
-            sort_by_query,
-        );
+        return match raw_query(&config, columns, &query).await {
+            Ok(rows) => query_response(rows, select_columns.is_some(), None),
+            Err(e) => Ok(Response::builder()
+                .status(if e.is_client_error() {
+                    StatusCode::BAD_REQUEST
+                } else if matches!(e, RawQueryError::Busy) {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(json_body(&e.to_json()))
+                .unwrap()),
+        };
+    }
 
-        param_count = bool_eq(
-            &mut sql,
-            &mut param_vec,
-            "bin",
-            &bin,
-            param_count,
-            sort_by_query,
-        );
-        param_count = bool_eq(
-            &mut sql,
-            &mut param_vec,
-            "recombobulated",
-            &recombobulated,
-            param_count,
-            sort_by_query,
-        );
-        param_count = bool_eq(
+    // Find and sort using query parameters
+    let mut sql = String::new();
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    let mut param_count = 1;
+
+    let sort_by_query = sort_by == "query";
+    let mut sort_by_query_end_sql = String::new();
+
+    if !sort_by_query {
+        if !bids.is_empty() {
+            // TODO: support bids in sort_by query
+            sql = format!(
+                "SELECT {} FROM query, unnest(bids) AS bid WHERE bid.bidder = $1",
+                columns
+            );
+            param_vec.push(&bids);
+            param_count += 1;
+        } else {
+            sql = format!("SELECT {} FROM query WHERE", columns);
+        }
+    }
+
+    param_count = int_eq(
+        &mut sql,
+        &mut param_vec,
+        "stars",
+        &stars,
+        param_count,
+        sort_by_query,
+    );
+    param_count = int_eq(
+        &mut sql,
+        &mut param_vec,
+        "potato_books",
+        &potato_books,
+        param_count,
+        sort_by_query,
+    );
+    param_count = int_eq(
+        &mut sql,
+        &mut param_vec,
+        "farming_for_dummies",
+        &farming_for_dummies,
+        param_count,
+        sort_by_query,
+    );
+    param_count = int_eq(
+        &mut sql,
+        &mut param_vec,
+        "transmission_tuner",
+        &transmission_tuner,
+        param_count,
+        sort_by_query,
+    );
+    param_count = int_eq(
+        &mut sql,
+        &mut param_vec,
+        "mana_disintegrator",
+        &mana_disintegrator,
+        param_count,
+        sort_by_query,
+    );
+
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "reforge",
+        &reforge,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "rune",
+        &rune,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "skin",
+        &skin,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "tier",
+        &tier,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "dye",
+        &dye,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "internal_id",
+        &internal_id,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "power_scroll",
+        &power_scroll,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "drill_upgrade_module",
+        &drill_upgrade_module,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "drill_fuel_tank",
+        &drill_fuel_tank,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "drill_engine",
+        &drill_engine,
+        param_count,
+        sort_by_query,
+    );
+    param_count = str_eq(
+        &mut sql,
+        &mut param_vec,
+        "accessory_enrichment",
+        &accessory_enrichment,
+        param_count,
+        sort_by_query,
+    );
+
+    param_count = bool_eq(
+        &mut sql,
+        &mut param_vec,
+        "bin",
+        &bin,
+        param_count,
+        sort_by_query,
+    );
+    param_count = bool_eq(
+        &mut sql,
+        &mut param_vec,
+        "recombobulated",
+        &recombobulated,
+        param_count,
+        sort_by_query,
+    );
+    param_count = bool_eq(
+        &mut sql,
+        &mut param_vec,
+        "wood_singularity",
+        &wood_singularity,
+        param_count,
+        sort_by_query,
+    );
+    param_count = bool_eq(
+        &mut sql,
+        &mut param_vec,
+        "art_of_war",
+        &art_of_war,
+        param_count,
+        sort_by_query,
+    );
+    param_count = bool_eq(
+        &mut sql,
+        &mut param_vec,
+        "art_of_peace",
+        &art_of_peace,
+        param_count,
+        sort_by_query,
+    );
+    param_count = bool_eq(
+        &mut sql,
+        &mut param_vec,
+        "etherwarp",
+        &etherwarp,
+        param_count,
+        sort_by_query,
+    );
+
+    let enchants_split: Vec<String>;
+    if !enchants.is_empty() {
+        enchants_split = enchants.split(',').map(|s| s.trim().to_string()).collect();
+        param_count = array_contains(
             &mut sql,
             &mut param_vec,
-            "wood_singularity",
-            &wood_singularity,
+            "enchants",
+            &enchants_split,
             param_count,
             sort_by_query,
         );
-        param_count = bool_eq(
+    }
+    let attributes_split: Vec<String>;
+    if !attributes.is_empty() {
+        attributes_split = attributes
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .collect();
+        param_count = array_contains(
             &mut sql,
             &mut param_vec,
-            "art_of_war",
-            &art_of_war,
+            "attributes",
+            &attributes_split,
             param_count,
             sort_by_query,
         );
-        param_count = bool_eq(
+    }
+    let necron_scrolls_split: Vec<String>;
+    if !necron_scrolls.is_empty() {
+        necron_scrolls_split = necron_scrolls
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .collect();
+        param_count = array_contains(
             &mut sql,
             &mut param_vec,
-            "art_of_peace",
-            &art_of_peace,
+            "necron_scrolls",
+            &necron_scrolls_split,
             param_count,
             sort_by_query,
         );
-        param_count = bool_eq(
+    }
+    let gemstones_split: Vec<String>;
+    if !gemstones.is_empty() {
+        gemstones_split = gemstones.split(',').map(|s| s.trim().to_string()).collect();
+        param_count = array_contains(
             &mut sql,
             &mut param_vec,
-            "etherwarp",
-            &etherwarp,
+            "gemstones",
+            &gemstones_split,
             param_count,
             sort_by_query,
         );
+    }
 
-        let enchants_split: Vec<String>;
-        if !enchants.is_empty() {
-            enchants_split = enchants.split(',').map(|s| s.trim().to_string()).collect();
-            param_count = array_contains(
-                &mut sql,
-                &mut param_vec,
-                "enchants",
-                &enchants_split,
-                param_count,
-                sort_by_query,
-            );
-        }
-        let attributes_split: Vec<String>;
-        if !attributes.is_empty() {
-            attributes_split = attributes
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .collect();
-            param_count = array_contains(
-                &mut sql,
-                &mut param_vec,
-                "attributes",
-                &attributes_split,
-                param_count,
-                sort_by_query,
-            );
-        }
-        let necron_scrolls_split: Vec<String>;
-        if !necron_scrolls.is_empty() {
-            necron_scrolls_split = necron_scrolls
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .collect();
-            param_count = array_contains(
-                &mut sql,
-                &mut param_vec,
-                "necron_scrolls",
-                &necron_scrolls_split,
-                param_count,
-                sort_by_query,
-            );
-        }
-        let gemstones_split: Vec<String>;
-        if !gemstones.is_empty() {
-            gemstones_split = gemstones.split(',').map(|s| s.trim().to_string()).collect();
-            param_count = array_contains(
-                &mut sql,
-                &mut param_vec,
-                "gemstones",
-                &gemstones_split,
-                param_count,
-                sort_by_query,
-            );
-        }
-
-        if !item_id.is_empty() {
-            if sort_by_query {
-                if !sort_by_query_end_sql.is_empty() {
-                    sort_by_query_end_sql.push_str(" AND");
-                }
-                sort_by_query_end_sql.push_str(&format!(" item_id = ${}", param_count));
-            } else {
-                if param_count != 1 {
-                    sql.push_str(" AND");
-                }
-                sql.push_str(&format!(" item_id = ${}", param_count));
+    if !item_id.is_empty() {
+        if sort_by_query {
+            if !sort_by_query_end_sql.is_empty() {
+                sort_by_query_end_sql.push_str(" AND");
             }
-            param_vec.push(&item_id);
-            param_count += 1;
+            sort_by_query_end_sql.push_str(&format!(" item_id = ${}", param_count));
+        } else {
+            if param_count != 1 {
+                sql.push_str(" AND");
+            }
+            sql.push_str(&format!(" item_id = ${}", param_count));
         }
-        if end >= 0 {
-            if sort_by_query {
-                if !sort_by_query_end_sql.is_empty() {
-                    sort_by_query_end_sql.push_str(" AND");
-                }
-                sort_by_query_end_sql.push_str(&format!(" end_t > ${}", param_count));
-            } else {
-                if param_count != 1 {
-                    sql.push_str(" AND");
-                }
-                sql.push_str(&format!(" end_t > ${}", param_count));
+        param_vec.push(&item_id);
+        param_count += 1;
+    }
+    if end >= 0 {
+        if sort_by_query {
+            if !sort_by_query_end_sql.is_empty() {
+                sort_by_query_end_sql.push_str(" AND");
             }
-            param_vec.push(&end);
-            param_count += 1;
+            sort_by_query_end_sql.push_str(&format!(" end_t > ${}", param_count));
+        } else {
+            if param_count != 1 {
+                sql.push_str(" AND");
+            }
+            sql.push_str(&format!(" end_t > ${}", param_count));
         }
-        if !item_name.is_empty() {
-            if sort_by_query {
-                if !sort_by_query_end_sql.is_empty() {
-                    sort_by_query_end_sql.push_str(" AND");
-                }
-                sort_by_query_end_sql.push_str(&format!(" item_name ILIKE ${}", param_count));
-            } else {
-                if param_count != 1 {
-                    sql.push_str(" AND");
-                }
-                sql.push_str(&format!(" item_name ILIKE ${}", param_count));
+        param_vec.push(&end);
+        param_count += 1;
+    }
+    if !item_name.is_empty() {
+        if sort_by_query {
+            if !sort_by_query_end_sql.is_empty() {
+                sort_by_query_end_sql.push_str(" AND");
             }
-            param_vec.push(&item_name);
-            param_count += 1;
+            sort_by_query_end_sql.push_str(&format!(" item_name ILIKE ${}", param_count));
+        } else {
+            if param_count != 1 {
+                sql.push_str(" AND");
+            }
+            sql.push_str(&format!(" item_name ILIKE ${}", param_count));
         }
-
-        // Handle unfinished WHERE
-        if sort_by_query && sort_by_query_end_sql.is_empty() {
-            sort_by_query_end_sql.push_str(" 1=1");
-        } else if param_count == 1 {
-            sql.push_str(" 1=1");
+        param_vec.push(&item_name);
+        param_count += 1;
+    }
+    // Range filters always have to match, even when sorting by query
+    param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "starting_bid",
+        &min_starting_bid,
+        &max_starting_bid,
+        param_count,
+        sort_by_query,
+    );
+    param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "highest_bid",
+        &min_highest_bid,
+        &max_highest_bid,
+        param_count,
+        sort_by_query,
+    );
+    param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "stars",
+        &min_stars,
+        &max_stars,
+        param_count,
+        sort_by_query,
+    );
+    param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "potato_books",
+        &min_potato_books,
+        &max_potato_books,
+        param_count,
+        sort_by_query,
+    );
+    param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "count",
+        &min_count,
+        &max_count,
+        param_count,
+        sort_by_query,
+    );
+    param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "end_t",
+        &min_end,
+        &max_end,
+        param_count,
+        sort_by_query,
+    );
+    param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        PRICE_PER_UNIT_SQL,
+        &min_price_per_unit,
+        &max_price_per_unit,
+        param_count,
+        sort_by_query,
+    );
+
+    if let Some(page_cursor) = &page_cursor {
+        if param_count != 1 {
+            sql.push_str(" AND");
         }
+        param_count = cursor_sql(&mut sql, &mut param_vec, page_cursor, param_count);
+    }
 
-        if sort_by_query {
-            sort_by_query_end_sql.push_str(" ORDER BY score DESC, cur_bid");
-        } else if (sort_by == "starting_bid" || sort_by == "highest_bid")
-            && (sort_order == "ASC" || sort_order == "DESC")
-        {
-            sql.push_str(&format!(" ORDER BY {} {}", sort_by, sort_order));
-        };
+    // Handle unfinished WHERE
+    if sort_by_query && sort_by_query_end_sql.is_empty() {
+        sort_by_query_end_sql.push_str(" 1=1");
+    } else if param_count == 1 {
+        sql.push_str(" 1=1");
+    }
 
-        if limit > 0 {
-            if sort_by_query {
-                sort_by_query_end_sql.push_str(&format!(" LIMIT ${}", param_count));
-            } else {
-                sql.push_str(&format!(" LIMIT ${}", param_count));
-            }
-            param_vec.push(&limit);
-        }
+    if sort_by_query {
+        sort_by_query_end_sql.push_str(" ORDER BY score DESC, cur_bid");
+    } else if paginate {
+        sql.push_str(&cursor_order_sql(page_sort_by, descending));
+    } else if (sort_by == "starting_bid" || sort_by == "highest_bid")
+        && (sort_order == "ASC" || sort_order == "DESC")
+    {
+        sql.push_str(&format!(" ORDER BY {} {}", sort_by, sort_order));
+    };
 
+    if limit > 0 {
         if sort_by_query {
-            sql = format!(
-                "SELECT *,{} AS score, GREATEST(starting_bid, highest_bid) AS cur_bid FROM query WHERE{}",
-                if sql.is_empty() { "0" } else { &sql },
-                sort_by_query_end_sql
-            );
+            sort_by_query_end_sql.push_str(&format!(" LIMIT ${}", param_count));
+        } else {
+            sql.push_str(&format!(" LIMIT ${}", param_count));
         }
+        param_vec.push(if paginate { &page_limit } else { &limit });
+    }
 
-        results_cursor = database_ref.query(&sql, &param_vec).await;
-    } else {
-        if !valid_api_key(config, key, true) {
-            return unauthorized();
+    if sort_by_query {
+        sql = format!(
+            "SELECT {},{} AS score, GREATEST(starting_bid, highest_bid) AS cur_bid FROM query WHERE{}",
+            columns,
+            if sql.is_empty() { "0" } else { &sql },
+            sort_by_query_end_sql
+        );
+    }
+
+    let results_cursor = get_client().await.query(&sql, &param_vec).await;
+
+    if let Err(e) = results_cursor {
+        return internal_error(&format!("Error when querying database: {}", e));
+    }
+
+    query_response(
+        results_cursor.unwrap(),
+        select_columns.is_some(),
+        paginate.then_some((limit, page_sort_by, descending)),
+    )
+}
+
+/* Finds auctions matching a JSON filter */
+async fn query_filter(
+    config: Arc<Config>,
+    req: Request<Incoming>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!(
+        "http://{}{}",
+        config.full_url,
+        &req.uri().to_string()
+    ))
+    .unwrap()
+    .query_pairs()
+    {
+        if query_pair.0 == "key" {
+            key = query_pair.1.to_string();
         }
+    }
 
-        results_cursor = database_ref
-            .query(&format!("SELECT * FROM query WHERE {}", query), &[])
-            .await;
+    if !valid_api_key(config.clone(), key.to_owned(), Scope::Query).await {
+        return unauthorized();
+    }
+
+    let body = match Limited::new(req.into_body(), QUERY_BODY_LIMIT)
+        .collect()
+        .await
+    {
+        Ok(body) => body.to_bytes(),
+        Err(e) => return bad_request(&format!("Error reading request body: {}", e)),
+    };
+    let request = match serde_json::from_slice::<QueryRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => return bad_request(&format!("Error parsing request body: {}", e)),
+    };
+
+    // Prevent fetching too many rows
+    if (request.limit <= 0 || request.limit >= 500)
+        && !valid_api_key(config.clone(), key.to_owned(), Scope::RawSql).await
+    {
+        return unauthorized();
+    }
+
+    let condition = match request.filter.map(Filter::resolve).transpose() {
+        Ok(condition) => condition,
+        Err(e) => return bad_request(&e),
+    };
+
+    let sort_by = request.sort_by.as_deref().unwrap_or("uuid");
+    if !["uuid", "starting_bid", "highest_bid", "end_t"].contains(&sort_by) {
+        return bad_request(
+            "The sort_by parameter must be uuid, starting_bid, highest_bid, or end_t",
+        );
+    }
+    let sort_order = request.sort_order.as_deref().unwrap_or("ASC");
+    if sort_order != "ASC" && sort_order != "DESC" {
+        return bad_request("The sort_order parameter must be ASC or DESC");
+    }
+    let descending = sort_order == "DESC";
+
+    let paginate = request.cursor.is_some();
+    let mut page_cursor = Option::None;
+    if let Some(cursor) = &request.cursor {
+        if request.limit <= 0 {
+            return bad_request("The limit parameter must be positive when using a cursor");
+        }
+        if !cursor.is_empty() {
+            match QueryCursor::decode(cursor, sort_by, descending) {
+                Ok(cursor) => page_cursor = Some(cursor),
+                Err(e) => return bad_request(&e),
+            }
+        }
     }
+    // Fetch one extra auction to know if there is another page
+    let page_limit = request.limit + 1;
 
+    // Cursors are created from the uuid and sort column
+    let required_columns = if paginate {
+        vec!["uuid", sort_by]
+    } else {
+        Vec::new()
+    };
+    let select_columns =
+        match get_select_columns(&request.fields, request.compact, &required_columns) {
+            Ok(select_columns) => select_columns,
+            Err(e) => return bad_request(&e),
+        };
+
+    let mut sql = format!(
+        "SELECT {} FROM query WHERE",
+        select_columns.as_deref().unwrap_or("*")
+    );
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    let mut param_count = 1;
+
+    match &condition {
+        Some(condition) => {
+            param_count = filter_sql(&mut sql, &mut param_vec, condition, param_count)
+        }
+        None => sql.push_str(" TRUE"),
+    }
+
+    if let Some(page_cursor) = &page_cursor {
+        sql.push_str(" AND");
+        param_count = cursor_sql(&mut sql, &mut param_vec, page_cursor, param_count);
+    }
+
+    if paginate {
+        sql.push_str(&cursor_order_sql(sort_by, descending));
+    } else if request.sort_by.is_some() {
+        sql.push_str(&format!(" ORDER BY {} {}", sort_by, sort_order));
+    }
+
+    if request.limit > 0 {
+        sql.push_str(&format!(" LIMIT ${}", param_count));
+        param_vec.push(if paginate {
+            &page_limit
+        } else {
+            &request.limit
+        });
+    }
+
+    let results_cursor = get_client().await.query(&sql, &param_vec).await;
     if let Err(e) = results_cursor {
         return internal_error(&format!("Error when querying database: {}", e));
     }
 
-    // Convert the cursor iterator to a vector
-    let results_vec = results_cursor
-        .unwrap()
-        .into_iter()
-        .map(QueryDatabaseItem::from)
-        .collect::<Vec<QueryDatabaseItem>>();
+    query_response(
+        results_cursor.unwrap(),
+        select_columns.is_some(),
+        paginate.then_some((request.limit, sort_by, descending)),
+    )
+}
 
-    // Return the vector of auctions serialized into JSON
+/* Runs the admin raw SQL query in a read only transaction with a timeout and a row limit, so it
+cannot block the updater from truncating the query table or use up the database connections */
+async fn raw_query(config: &Config, columns: &str, query: &str) -> Result<Vec<Row>, RawQueryError> {
+    let _permit = RAW_QUERY_PERMITS
+        .try_acquire()
+        .map_err(|_| RawQueryError::Busy)?;
+
+    let mut database_ref = get_client().await;
+    let transaction = database_ref
+        .build_transaction()
+        .read_only(true)
+        .start()
+        .await?;
+    transaction
+        .batch_execute(&format!(
+            "SET LOCAL statement_timeout = {}; SET LOCAL lock_timeout = {}",
+            config.raw_query_timeout_ms, config.raw_query_timeout_ms
+        ))
+        .await?;
+
+    // Fetch one extra row to know if the query went over the limit
+    let prefix = format!("SELECT * FROM (SELECT {} FROM query WHERE ", columns);
+    let rows = transaction
+        .query(
+            &format!("{}{}) AS raw_query LIMIT $1", prefix, query),
+            &[&(config.raw_query_row_limit + 1)],
+        )
+        .await
+        .map_err(|e| RawQueryError::Query(e, prefix.chars().count() as u32))?;
+    transaction.rollback().await?;
+
+    if rows.len() as i64 > config.raw_query_row_limit {
+        return Err(RawQueryError::RowLimit(config.raw_query_row_limit));
+    }
+
+    Ok(rows)
+}
+
+/* Serializes the auctions of a query, as a page if paginating. If only some columns were
+selected, only those are serialized */
+fn query_response(
+    rows: Vec<Row>,
+    projected: bool,
+    page: Option<(i64, &str, bool)>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let body = match page {
+        Some((limit, sort_by, descending)) => {
+            if projected {
+                json_body(&QueryPage::new(rows, limit, sort_by, descending, |row| {
+                    row_to_json(&row)
+                }))
+            } else {
+                json_body(&QueryPage::new(
+                    rows,
+                    limit,
+                    sort_by,
+                    descending,
+                    QueryDatabaseItem::from,
+                ))
+            }
+        }
+        None => {
+            if projected {
+                json_body(&rows.iter().map(row_to_json).collect::<Vec<_>>())
+            } else {
+                json_body(
+                    &rows
+                        .into_iter()
+                        .map(QueryDatabaseItem::from)
+                        .collect::<Vec<QueryDatabaseItem>>(),
+                )
+            }
+        }
+    };
+
+    // Return the auctions serialized into JSON
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .body(json_body(&results_vec))
+        .body(body)
         .unwrap())
 }
 
@@ -964,19 +1680,29 @@ async fn query_items(
         }
     }
 
-    if !valid_api_key(config, key, false) {
+    if !valid_api_key(config, key, Scope::Query).await {
         return unauthorized();
     }
 
-    let file_result = fs::read("query_items.json");
-    if file_result.is_err() {
-        return internal_error("Unable to open or read query_items.json");
+    let results_cursor = get_client()
+        .await
+        .query("SELECT name FROM query_items", &[])
+        .await;
+
+    if let Err(e) = results_cursor {
+        return internal_error(&format!("Error when querying database: {}", e));
     }
 
+    let results_vec = results_cursor
+        .unwrap()
+        .into_iter()
+        .map(|row| row.get("name"))
+        .collect::<Vec<String>>();
+
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .body(file_body(file_result))
+        .body(json_body(&results_vec))
         .unwrap())
 }
 
@@ -996,19 +1722,652 @@ async fn lowestbin(
         }
     }
 
-    if !valid_api_key(config, key, false) {
+    if !valid_api_key(config, key, Scope::Lowestbin).await {
         return unauthorized();
     }
 
-    let file_result = fs::read("lowestbin.json");
-    if file_result.is_err() {
-        return internal_error("Unable to open or read lowestbin.json");
+    let results_cursor = get_client()
+        .await
+        .query("SELECT item_id, price FROM lowestbin", &[])
+        .await;
+
+    if let Err(e) = results_cursor {
+        return internal_error(&format!("Error when querying database: {}", e));
     }
 
+    let results_map = results_cursor
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get("item_id"), row.get("price")))
+        .collect::<DashMap<String, f32>>();
+
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .body(file_body(file_result))
+        .body(json_body(&results_map))
+        .unwrap())
+}
+
+async fn lowestbin_history(
+    config: Arc<Config>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+    let mut item_id = String::new();
+    let mut time = 0;
+    let mut step = 60;
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+    {
+        match query_pair.0.to_string().as_str() {
+            "key" => key = query_pair.1.to_string(),
+            "item_id" => item_id = query_pair.1.to_string(),
+            "time" => match query_pair.1.to_string().parse::<i32>() {
+                Ok(time_int) => time = time_int,
+                Err(e) => return bad_request(&format!("Error parsing time parameter: {}", e)),
+            },
+            "step" => match query_pair.1.to_string().parse::<i32>() {
+                Ok(step_int) => step = step_int,
+                Err(e) => return bad_request(&format!("Error parsing step parameter: {}", e)),
+            },
+            _ => {}
+        }
+    }
+
+    if !valid_api_key(config, key, Scope::Lowestbin).await {
+        return unauthorized();
+    }
+
+    if item_id.is_empty() {
+        return bad_request("The item_id parameter cannot be empty");
+    }
+
+    if time < 0 {
+        return bad_request("The time parameter cannot be negative");
+    }
+
+    if step <= 0 {
+        return bad_request("The step parameter must be positive");
+    }
+
+    let Some(step_secs) = step.checked_mul(60) else {
+        return bad_request("The step parameter is too large");
+    };
+    let item_ids: Vec<String> = item_id.split(',').map(|s| s.trim().to_string()).collect();
+
+    // Lowest price of each item in each step
+    let results_cursor = get_client()
+        .await
+        .query(
+            "SELECT item_id, (time_t / $1) * $1 AS time, MIN(price) AS price FROM lowestbin_history WHERE item_id = ANY($2) AND time_t > $3 GROUP BY item_id, time ORDER BY time",
+            &[&step_secs, &item_ids, &time],
+        )
+        .await;
+
+    if let Err(e) = results_cursor {
+        return internal_error(&format!("Error when querying database: {}", e));
+    }
+
+    let history_map: DashMap<String, Vec<LowestBinHistoryItem>> = DashMap::new();
+    for row in results_cursor.unwrap() {
+        history_map
+            .entry(row.get("item_id"))
+            .or_default()
+            .push(LowestBinHistoryItem {
+                time: row.get("time"),
+                price: row.get("price"),
+            });
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(json_body(&history_map))
+        .unwrap())
+}
+
+async fn history(
+    config: Arc<Config>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+    let mut item_id = String::new();
+    let mut source = String::from("all");
+    let mut time = 0;
+    let mut step = 60;
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+    {
+        match query_pair.0.to_string().as_str() {
+            "key" => key = query_pair.1.to_string(),
+            "item_id" => item_id = query_pair.1.to_string(),
+            "source" => source = query_pair.1.to_string(),
+            "time" => match query_pair.1.to_string().parse::<i32>() {
+                Ok(time_int) => time = time_int,
+                Err(e) => return bad_request(&format!("Error parsing time parameter: {}", e)),
+            },
+            "step" => match query_pair.1.to_string().parse::<i32>() {
+                Ok(step_int) => step = step_int,
+                Err(e) => return bad_request(&format!("Error parsing step parameter: {}", e)),
+            },
+            _ => {}
+        }
+    }
+
+    let tables = match source.as_str() {
+        "auction" if config.is_enabled(Feature::AverageAuction) => vec!["average_auction"],
+        "bin" if config.is_enabled(Feature::AverageBin) => vec!["average_bin"],
+        "all"
+            if config.is_enabled(Feature::AverageAuction)
+                && config.is_enabled(Feature::AverageBin) =>
+        {
+            vec!["average_auction", "average_bin"]
+        }
+        "auction" | "bin" | "all" => {
+            return bad_request("Average feature for this source is not enabled")
+        }
+        _ => return bad_request("The source parameter must be 'auction', 'bin', or 'all'"),
+    };
+
+    if !valid_api_key(config, key, Scope::Averages).await {
+        return unauthorized();
+    }
+
+    if item_id.is_empty() {
+        return bad_request("The item_id parameter cannot be empty");
+    }
+
+    if time < 0 {
+        return bad_request("The time parameter cannot be negative");
+    }
+
+    if step <= 0 {
+        return bad_request("The step parameter must be positive");
+    }
+
+    let Some(step_secs) = step.checked_mul(60) else {
+        return bad_request("The step parameter is too large");
+    };
+    let item_ids: Vec<String> = item_id.split(',').map(|s| s.trim().to_string()).collect();
+
+    // Map each item id to the average price and sales of each minute
+    let prices_map: DashMap<String, Vec<(i32, AvgAh)>> = DashMap::new();
+
+    for table in tables {
+        let results_cursor = get_client()
+            .await
+            .query(
+                &format!("SELECT item_id, time_t, price, sales FROM {table} WHERE item_id = ANY($1) AND time_t > $2"),
+                &[&item_ids, &time],
+            )
+            .await;
+
+        if let Err(e) = results_cursor {
+            return internal_error(&format!("Error when querying database: {}", e));
+        }
+
+        for row in results_cursor.unwrap() {
+            prices_map.entry(row.get("item_id")).or_default().push((
+                row.get("time_t"),
+                AvgAh {
+                    price: row.get("price"),
+                    sales: row.get("sales"),
+                },
+            ));
+        }
+    }
+
+    let history_map: DashMap<String, Vec<AverageHistoryItem>> = prices_map
+        .into_iter()
+        .map(|(item_id, prices)| (item_id, AverageHistoryItem::from_prices(prices, step_secs)))
+        .collect();
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(json_body(&history_map))
+        .unwrap())
+}
+
+/* Streams new auctions, under bins, and ended auctions as server-sent events */
+async fn stream(
+    config: Arc<Config>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+    let mut filter = StreamFilter::default();
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+    {
+        match query_pair.0.to_string().as_str() {
+            "key" => key = query_pair.1.to_string(),
+            "event" => {
+                filter.events = query_pair
+                    .1
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .collect()
+            }
+            "item_id" => {
+                filter.item_ids = query_pair
+                    .1
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .collect()
+            }
+            "tier" => filter.tier = Some(query_pair.1.to_string()),
+            "bin" => match query_pair.1.to_string().parse::<bool>() {
+                Ok(bin_bool) => filter.bin = Some(bin_bool),
+                Err(e) => return bad_request(&format!("Error parsing bin parameter: {}", e)),
+            },
+            "max_price" => match query_pair.1.to_string().parse::<i64>() {
+                Ok(max_price_int) => filter.max_price = Some(max_price_int),
+                Err(e) => return bad_request(&format!("Error parsing max_price parameter: {}", e)),
+            },
+            _ => {}
+        }
+    }
+
+    if !valid_api_key(config.clone(), key.clone(), Scope::Stream).await {
+        return unauthorized();
+    }
+
+    if let Some(event) = filter
+        .events
+        .iter()
+        .find(|event| !["auction", "underbin", "ended_auction"].contains(&event.as_str()))
+    {
+        return bad_request(&format!("Unknown event {}", event));
+    }
+
+    let Some(receiver) = subscribe(config.stream_max_subscribers) else {
+        return http_err(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many stream subscribers, try again later",
+        );
+    };
+    let state = (receiver, filter, config, key, Instant::now());
+    let events = futures::stream::unfold(state, |mut state| async move {
+        let (receiver, filter, config, key, key_checked) = &mut state;
+        loop {
+            // Closes the stream once the key is revoked or expires
+            if key_checked.elapsed() >= STREAM_KEY_CHECK_INTERVAL {
+                // Spawned since the body has to be Sync, which the lookup future is not
+                let check = tokio::spawn(valid_api_key(config.clone(), key.clone(), Scope::Stream));
+                if !check.await.unwrap_or(false) {
+                    return None;
+                }
+                *key_checked = Instant::now();
+            }
+
+            let frame = match tokio::time::timeout(Duration::from_secs(15), receiver.recv()).await {
+                Ok(Ok(event)) => {
+                    if !filter.matches(&event) {
+                        continue;
+                    }
+                    event.to_sse()
+                }
+                // Too slow to keep up, so some events were dropped
+                Ok(Err(RecvError::Lagged(_))) => continue,
+                Ok(Err(RecvError::Closed)) => return None,
+                // Comment to keep idle connections open
+                Err(_) => String::from(": keep-alive\n\n"),
+            };
+
+            return Some((
+                Ok::<Frame<Bytes>, Error>(Frame::data(Bytes::from(frame))),
+                state,
+            ));
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(StreamBody::new(events).boxed())
+        .unwrap())
+}
+
+/* Lists, creates, or deletes alert rules */
+async fn alerts(
+    config: Arc<Config>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+    let mut id = None;
+    let mut rule = AlertRule {
+        id: 0,
+        name: String::new(),
+        webhook_url: String::new(),
+        webhook_type: String::from("DISCORD"),
+        item_id: None,
+        internal_id: None,
+        tier: None,
+        bin: None,
+        max_price: None,
+        min_stars: None,
+        recombobulated: None,
+        enchants: Vec::new(),
+        created_t: get_timestamp_millis() as i64,
+    };
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+    {
+        match query_pair.0.to_string().as_str() {
+            "key" => key = query_pair.1.to_string(),
+            "id" => match query_pair.1.to_string().parse::<i32>() {
+                Ok(id_int) => id = Some(id_int),
+                Err(e) => return bad_request(&format!("Error parsing id parameter: {}", e)),
+            },
+            "name" => rule.name = query_pair.1.to_string(),
+            "webhook_url" => rule.webhook_url = query_pair.1.to_string(),
+            "webhook_type" => rule.webhook_type = query_pair.1.to_uppercase(),
+            "item_id" => rule.item_id = Some(query_pair.1.to_string()),
+            "internal_id" => rule.internal_id = Some(query_pair.1.to_string()),
+            "tier" => rule.tier = Some(query_pair.1.to_string()),
+            "bin" => match query_pair.1.to_string().parse::<bool>() {
+                Ok(bin_bool) => rule.bin = Some(bin_bool),
+                Err(e) => return bad_request(&format!("Error parsing bin parameter: {}", e)),
+            },
+            "max_price" => match query_pair.1.to_string().parse::<i64>() {
+                Ok(max_price_int) => rule.max_price = Some(max_price_int),
+                Err(e) => return bad_request(&format!("Error parsing max_price parameter: {}", e)),
+            },
+            "min_stars" => match query_pair.1.to_string().parse::<i16>() {
+                Ok(min_stars_int) => rule.min_stars = Some(min_stars_int),
+                Err(e) => return bad_request(&format!("Error parsing min_stars parameter: {}", e)),
+            },
+            "recombobulated" => match query_pair.1.to_string().parse::<bool>() {
+                Ok(recombobulated_bool) => rule.recombobulated = Some(recombobulated_bool),
+                Err(e) => {
+                    return bad_request(&format!("Error parsing recombobulated parameter: {}", e))
+                }
+            },
+            "enchants" => {
+                rule.enchants = query_pair
+                    .1
+                    .split(',')
+                    .map(|s| s.trim().to_uppercase())
+                    .collect()
+            }
+            _ => {}
+        }
+    }
+
+    // Keys with the alerts scope can manage alerts, but only admins can send them to any URL
+    let is_admin = valid_api_key(config.clone(), key.clone(), Scope::Admin).await;
+    if !is_admin && !valid_api_key(config, key, Scope::Alerts).await {
+        return unauthorized();
+    }
+
+    let database = get_client().await;
+    match req.uri().path() {
+        "/alerts/create" => {
+            if rule.name.is_empty() {
+                return bad_request("The name parameter cannot be empty");
+            }
+            if rule.webhook_url.is_empty() {
+                return bad_request("The webhook_url parameter cannot be empty");
+            }
+            if rule.webhook_type != "DISCORD" && rule.webhook_type != "HTTP" {
+                return bad_request("The webhook_type parameter must be 'DISCORD' or 'HTTP'");
+            }
+            if !is_admin {
+                if let Err(e) = check_webhook_url(&rule.webhook_type, &rule.webhook_url) {
+                    return bad_request(&e);
+                }
+            }
+            if !rule.has_conditions() {
+                return bad_request("Alerts must have at least one condition");
+            }
+
+            match database
+                .query_one(
+                    "INSERT INTO alerts (name, webhook_url, webhook_type, item_id, internal_id, tier, bin, max_price, min_stars, recombobulated, enchants, created_t) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id",
+                    &[
+                        &rule.name,
+                        &rule.webhook_url,
+                        &rule.webhook_type,
+                        &rule.item_id,
+                        &rule.internal_id,
+                        &rule.tier,
+                        &rule.bin,
+                        &rule.max_price,
+                        &rule.min_stars,
+                        &rule.recombobulated,
+                        &rule.enchants,
+                        &rule.created_t,
+                    ],
+                )
+                .await
+            {
+                Ok(row) => rule.id = row.get("id"),
+                Err(e) => return internal_error(&format!("Error when inserting alert: {}", e)),
+            }
+
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(json_body(&rule))
+                .unwrap())
+        }
+        "/alerts/delete" => {
+            let Some(id) = id else {
+                return bad_request("The id parameter cannot be empty");
+            };
+
+            match database
+                .execute("DELETE FROM alerts WHERE id = $1", &[&id])
+                .await
+            {
+                Ok(0) => bad_request(&format!("No alert with id {}", id)),
+                Ok(_) => Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(json_body(&json!({"success": true})))
+                    .unwrap()),
+                Err(e) => internal_error(&format!("Error when deleting alert: {}", e)),
+            }
+        }
+        _ => match database
+            .query("SELECT * FROM alerts ORDER BY id", &[])
+            .await
+        {
+            Ok(rows) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(json_body(
+                    &rows.into_iter().map(AlertRule::from).collect::<Vec<_>>(),
+                ))
+                .unwrap()),
+            Err(e) => internal_error(&format!("Error when querying database: {}", e)),
+        },
+    }
+}
+
+async fn keys(
+    config: Arc<Config>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+    let mut id = Option::None;
+    let mut label = String::new();
+    let mut scopes = Vec::new();
+    let mut expires_at = Option::None;
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+    {
+        match query_pair.0.to_string().as_str() {
+            "key" => key = query_pair.1.to_string(),
+            "id" => match query_pair.1.to_string().parse::<i32>() {
+                Ok(id_int) => id = Some(id_int),
+                Err(e) => return bad_request(&format!("Error parsing id parameter: {}", e)),
+            },
+            "label" => label = query_pair.1.to_string(),
+            "scopes" => {
+                for scope in query_pair.1.split(',') {
+                    match scope.trim().parse::<Scope>() {
+                        Ok(scope) => scopes.push(scope),
+                        Err(e) => return bad_request(&e),
+                    }
+                }
+            }
+            "expires_at" => match query_pair.1.to_string().parse::<i64>() {
+                Ok(expires_at_int) => expires_at = Some(expires_at_int),
+                Err(e) => {
+                    return bad_request(&format!("Error parsing expires_at parameter: {}", e))
+                }
+            },
+            _ => {}
+        }
+    }
+
+    if !valid_api_key(config, key, Scope::Admin).await {
+        return unauthorized();
+    }
+
+    match req.uri().path() {
+        "/keys/create" => {
+            if label.is_empty() {
+                return bad_request("The label parameter cannot be empty");
+            }
+            if scopes.is_empty() {
+                return bad_request("The scopes parameter cannot be empty");
+            }
+            let now = get_timestamp_millis() as i64;
+            if expires_at.is_some_and(|expires_at| expires_at <= now) {
+                return bad_request("The expires_at parameter must be in the future");
+            }
+
+            match create_api_key(&label, &scopes, now, expires_at).await {
+                // The full key is only ever returned here
+                Ok((api_key, key)) => Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(json_body(&json!({"key": key, "api_key": api_key})))
+                    .unwrap()),
+                Err(e) => internal_error(&format!("Error when inserting key: {}", e)),
+            }
+        }
+        "/keys/revoke" => {
+            let Some(id) = id else {
+                return bad_request("The id parameter cannot be empty");
+            };
+
+            match revoke_api_key(id).await {
+                Ok(false) => bad_request(&format!("No key with id {}", id)),
+                Ok(true) => Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(json_body(&json!({"success": true})))
+                    .unwrap()),
+                Err(e) => internal_error(&format!("Error when revoking key: {}", e)),
+            }
+        }
+        _ => match list_api_keys().await {
+            Ok(api_keys) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(json_body(&api_keys))
+                .unwrap()),
+            Err(e) => internal_error(&format!("Error when querying database: {}", e)),
+        },
+    }
+}
+
+/* Reports the usage of each key and endpoint, defaulting to the last day */
+async fn usage(
+    config: Arc<Config>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+    let mut to = get_timestamp_millis() as i64;
+    let mut from = Option::None;
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+    {
+        match query_pair.0.to_string().as_str() {
+            "key" => key = query_pair.1.to_string(),
+            "from" => match query_pair.1.to_string().parse::<i64>() {
+                Ok(from_int) => from = Some(from_int),
+                Err(e) => return bad_request(&format!("Error parsing from parameter: {}", e)),
+            },
+            "to" => match query_pair.1.to_string().parse::<i64>() {
+                Ok(to_int) => to = to_int,
+                Err(e) => return bad_request(&format!("Error parsing to parameter: {}", e)),
+            },
+            _ => {}
+        }
+    }
+
+    if !valid_api_key(config, key, Scope::Admin).await {
+        return unauthorized();
+    }
+
+    let from = from.unwrap_or(to - 86400000);
+    if from >= to {
+        return bad_request("The from parameter must be before the to parameter");
+    }
+
+    match get_usage_report(from, to).await {
+        Ok(usage) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(json_body(&json!({
+                "success": true,
+                "from": from,
+                "to": to,
+                "usage": usage,
+            })))
+            .unwrap()),
+        Err(e) => internal_error(&format!("Error when querying database: {}", e)),
+    }
+}
+
+/* Prometheus metrics in the text format */
+async fn metrics(
+    config: Arc<Config>,
+    req: Request<impl Body>,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let mut key = String::new();
+
+    // Reads the query parameters from the request and stores them in the corresponding variable
+    for query_pair in Url::parse(&format!("http://{}{}", config.full_url, &req.uri()))
+        .unwrap()
+        .query_pairs()
+    {
+        if query_pair.0 == "key" {
+            key = query_pair.1.to_string();
+        }
+    }
+
+    if !valid_api_key(config, key, Scope::Metrics).await {
+        return unauthorized();
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(text_body(get_metrics().await))
         .unwrap())
 }
 
@@ -1028,19 +2387,29 @@ async fn underbin(
         }
     }
 
-    if !valid_api_key(config, key, false) {
+    if !valid_api_key(config, key, Scope::Underbin).await {
         return unauthorized();
     }
 
-    let file_result = fs::read("underbin.json");
-    if file_result.is_err() {
-        return internal_error("Unable to open or read underbin.json");
+    let results_cursor = get_client()
+        .await
+        .query("SELECT uuid, data FROM underbin", &[])
+        .await;
+
+    if let Err(e) = results_cursor {
+        return internal_error(&format!("Error when querying database: {}", e));
     }
 
+    let results_map = results_cursor
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get("uuid"), row.get("data")))
+        .collect::<DashMap<String, Value>>();
+
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .body(file_body(file_result))
+        .body(json_body(&results_map))
         .unwrap())
 }
 
@@ -1148,13 +2517,26 @@ fn param_eq<'a>(
         sql.push_str(" CASE WHEN")
     }
 
-    sql.push_str(&format!(" {} = ${}", param_name, param_count));
-    param_vec.push(param_value);
+    let param_count = param_cmp(sql, param_vec, param_name, "=", param_value, param_count);
 
     if sort_by_query {
         sql.push_str(" THEN 1 ELSE 0 END")
     }
 
+    param_count
+}
+
+fn param_cmp<'a>(
+    sql: &mut String,
+    param_vec: &mut Vec<&'a (dyn ToSql + Sync)>,
+    param_name: &str,
+    operator: &str,
+    param_value: &'a (dyn ToSql + Sync),
+    param_count: i32,
+) -> i32 {
+    sql.push_str(&format!(" {} {} ${}", param_name, operator, param_count));
+    param_vec.push(param_value);
+
     param_count + 1
 }
 
@@ -1170,19 +2552,36 @@ fn array_contains<'a>(
         sql.push_str(if sort_by_query { " +" } else { " AND" });
     }
 
-    let mut param_count_mut = param_count;
-
     if sort_by_query {
-        sql.push_str(" cardinality(ARRAY(SELECT unnest(ARRAY[");
+        sql.push_str(" cardinality(ARRAY(SELECT unnest(");
     } else {
         sql.push(' ');
         sql.push_str(param_name);
-        sql.push_str(" @> ARRAY[");
+        sql.push_str(" @> ");
+    }
+
+    let param_count_mut = array_params(sql, param_vec, param_value, param_count);
+
+    if sort_by_query {
+        sql.push_str(") intersect SELECT unnest(");
+        sql.push_str(param_name);
+        sql.push_str(")))");
     }
 
-    let start_param_count = param_count;
+    param_count_mut
+}
+
+fn array_params<'a>(
+    sql: &mut String,
+    param_vec: &mut Vec<&'a (dyn ToSql + Sync)>,
+    param_value: &'a [String],
+    param_count: i32,
+) -> i32 {
+    let mut param_count_mut = param_count;
+
+    sql.push_str("ARRAY[");
     for enchant in param_value.iter() {
-        if param_count_mut != start_param_count {
+        if param_count_mut != param_count {
             sql.push(',');
         }
 
@@ -1190,12 +2589,131 @@ fn array_contains<'a>(
         param_vec.push(enchant);
         param_count_mut += 1;
     }
-
     sql.push(']');
-    if sort_by_query {
-        sql.push_str(") intersect SELECT unnest(");
-        sql.push_str(param_name);
-        sql.push_str(")))");
+
+    param_count_mut
+}
+
+/* Continues after the last auction of the previous page */
+fn cursor_sql<'a>(
+    sql: &mut String,
+    param_vec: &mut Vec<&'a (dyn ToSql + Sync)>,
+    page_cursor: &'a QueryCursor,
+    param_count: i32,
+) -> i32 {
+    let operator = if page_cursor.descending { "<" } else { ">" };
+
+    match &page_cursor.value {
+        Some(value) => {
+            sql.push_str(&format!(
+                " ({}, uuid) {} (${}, ${})",
+                page_cursor.sort_by,
+                operator,
+                param_count,
+                param_count + 1
+            ));
+            param_vec.push(value);
+            param_vec.push(&page_cursor.uuid);
+            param_count + 2
+        }
+        None => param_cmp(
+            sql,
+            param_vec,
+            "uuid",
+            operator,
+            &page_cursor.uuid,
+            param_count,
+        ),
+    }
+}
+
+fn cursor_order_sql(sort_by: &str, descending: bool) -> String {
+    let sort_order = if descending { "DESC" } else { "ASC" };
+    if sort_by == "uuid" {
+        format!(" ORDER BY uuid {}", sort_order)
+    } else {
+        format!(" ORDER BY {} {}, uuid {}", sort_by, sort_order, sort_order)
+    }
+}
+
+/* Writes a JSON filter as a WHERE condition */
+fn filter_sql<'a>(
+    sql: &mut String,
+    param_vec: &mut Vec<&'a (dyn ToSql + Sync)>,
+    condition: &'a Condition,
+    param_count: i32,
+) -> i32 {
+    let mut param_count_mut = param_count;
+
+    match condition {
+        Condition::And(conditions) | Condition::Or(conditions) => {
+            let (joiner, empty) = if matches!(condition, Condition::And(_)) {
+                (" AND", " TRUE")
+            } else {
+                (" OR", " FALSE")
+            };
+
+            if conditions.is_empty() {
+                sql.push_str(empty);
+            } else {
+                sql.push_str(" (");
+                for (i, condition) in conditions.iter().enumerate() {
+                    if i != 0 {
+                        sql.push_str(joiner);
+                    }
+                    param_count_mut = filter_sql(sql, param_vec, condition, param_count_mut);
+                }
+                sql.push_str(" )");
+            }
+        }
+        Condition::Not(condition) => {
+            sql.push_str(" NOT (");
+            param_count_mut = filter_sql(sql, param_vec, condition, param_count_mut);
+            sql.push_str(" )");
+        }
+        Condition::Eq { column, value } => {
+            param_count_mut =
+                param_cmp(sql, param_vec, column, "=", value.as_sql(), param_count_mut);
+        }
+        Condition::In { column, values } => {
+            if values.is_empty() {
+                sql.push_str(" FALSE");
+            } else {
+                sql.push_str(&format!(" {} IN (", column));
+                for value in values {
+                    if param_count_mut != param_count {
+                        sql.push(',');
+                    }
+                    sql.push_str(&format!("${}", param_count_mut));
+                    param_vec.push(value.as_sql());
+                    param_count_mut += 1;
+                }
+                sql.push(')');
+            }
+        }
+        Condition::Range { column, min, max } => {
+            sql.push_str(" (");
+            if let Some(min) = min {
+                param_count_mut =
+                    param_cmp(sql, param_vec, column, ">=", min.as_sql(), param_count_mut);
+            }
+            if let Some(max) = max {
+                if min.is_some() {
+                    sql.push_str(" AND");
+                }
+                param_count_mut =
+                    param_cmp(sql, param_vec, column, "<=", max.as_sql(), param_count_mut);
+            }
+            sql.push_str(" )");
+        }
+        Condition::Contains { column, values } => {
+            if values.is_empty() {
+                sql.push_str(" TRUE");
+            } else {
+                sql.push_str(&format!(" {} @> ", column));
+                param_count_mut = array_params(sql, param_vec, values, param_count_mut);
+            }
+        }
     }
 
     param_count_mut
@@ -1238,6 +2756,10 @@ where
         .boxed()
 }
 
+fn text_body(text: String) -> BoxBody<Bytes, Error> {
+    Full::from(text).map_err(|never| match never {}).boxed()
+}
+
 fn file_body(file: Result<Vec<u8>, std::io::Error>) -> BoxBody<Bytes, Error> {
     Full::from(file.unwrap())
         .map_err(|never| match never {})
@@ -0,0 +1,159 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Record-and-replay of upstream responses. Each run is stored as
+//! `{SNAPSHOT_DIR}/{run start in ms}/` containing `auctions/{page}.json`,
+//! `auctions_ended.json` and `run.json`, which is also a valid `MOCK_DATA_DIR`.
+
+use crate::{
+    api_handler::update_auctions,
+    config::{Config, SnapshotMode},
+    statics::{HTTP_CLIENT, SNAPSHOT},
+    utils::{error, info},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fs, io, path::PathBuf, sync::Arc};
+
+/* Metadata needed to replay a run exactly as it was recorded */
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotRun {
+    pub last_updated: i64,
+}
+
+/* Fetches an upstream endpoint, recording or replaying the response if enabled */
+pub async fn get_upstream<T: DeserializeOwned>(
+    config: &Config,
+    url_path: &str,
+    snapshot_path: &str,
+) -> Option<T> {
+    let body = if config.snapshot_mode == Some(SnapshotMode::Replay) {
+        let snapshot = SNAPSHOT.lock().await.clone()?;
+        fs::read_to_string(snapshot.join(snapshot_path)).ok()?
+    } else {
+        let body = HTTP_CLIENT
+            .get(format!("{}{}", config.hypixel_api_url, url_path))
+            .send()
+            .await
+            .ok()?
+            .text()
+            .await
+            .ok()?;
+
+        if config.snapshot_mode == Some(SnapshotMode::Record) {
+            if let Some(snapshot) = SNAPSHOT.lock().await.as_ref() {
+                let path = snapshot.join(snapshot_path);
+                if let Err(e) = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&path, &body))
+                {
+                    error(format!("Error writing snapshot {}: {}", path.display(), e));
+                }
+            }
+        }
+
+        body
+    };
+
+    serde_json::from_str(&body).ok()
+}
+
+/* Creates the snapshot directory for a new run when recording */
+pub async fn begin_snapshot(config: &Config, started_epoch: i64, last_updated: i64) {
+    if config.snapshot_mode != Some(SnapshotMode::Record) {
+        return;
+    }
+
+    let snapshot = PathBuf::from(&config.snapshot_dir).join(started_epoch.to_string());
+    let run = serde_json::to_string(&SnapshotRun { last_updated }).unwrap();
+    if let Err(e) =
+        fs::create_dir_all(&snapshot).and_then(|_| fs::write(snapshot.join("run.json"), run))
+    {
+        error(format!(
+            "Error creating snapshot {}: {}",
+            snapshot.display(),
+            e
+        ));
+        return;
+    }
+
+    let _ = SNAPSHOT.lock().await.insert(snapshot);
+}
+
+/* Removes the current recording if the run was cancelled */
+pub async fn discard_snapshot(config: &Config) {
+    if config.snapshot_mode != Some(SnapshotMode::Record) {
+        return;
+    }
+
+    if let Some(snapshot) = SNAPSHOT.lock().await.take() {
+        let _ = fs::remove_dir_all(snapshot);
+    }
+}
+
+/* Gets the recorded last updated value of the snapshot being replayed */
+pub async fn get_replay_last_updated(config: &Config) -> Option<i64> {
+    if config.snapshot_mode != Some(SnapshotMode::Replay) {
+        return None;
+    }
+
+    let snapshot = SNAPSHOT.lock().await.clone()?;
+    fs::read_to_string(snapshot.join("run.json"))
+        .ok()
+        .and_then(|run| serde_json::from_str::<SnapshotRun>(&run).ok())
+        .map(|run| run.last_updated)
+}
+
+/* Gets the recorded runs in the snapshot directory in chronological order */
+pub fn get_snapshots(snapshot_dir: &str) -> io::Result<Vec<PathBuf>> {
+    let mut snapshots = fs::read_dir(snapshot_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().parse::<i64>().ok()?;
+            Some((name, entry.path()))
+        })
+        .collect::<Vec<(i64, PathBuf)>>();
+    snapshots.sort_by_key(|snapshot| snapshot.0);
+
+    Ok(snapshots
+        .into_iter()
+        .map(|(_, snapshot)| snapshot)
+        .collect())
+}
+
+/* Runs every recorded snapshot through the update pipeline in chronological order */
+pub async fn replay_snapshots(config: Arc<Config>) {
+    let snapshots = match get_snapshots(&config.snapshot_dir) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            error(format!(
+                "Unable to read snapshot directory {}: {}",
+                config.snapshot_dir, e
+            ));
+            return;
+        }
+    };
+
+    info(format!("Replaying {} snapshots...", snapshots.len()));
+    for snapshot in snapshots {
+        let _ = SNAPSHOT.lock().await.insert(snapshot);
+        update_auctions(config.clone()).await;
+    }
+    let _ = SNAPSHOT.lock().await.take();
+    info(String::from("Finished replaying snapshots"));
+}
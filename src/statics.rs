@@ -16,13 +16,20 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::webhook::Webhook;
+use crate::{
+    api_keys::ApiKey, metrics::Metrics, notifier::Notification, rate_limit::Usage,
+    stream::StreamEvent, usage::UsageRecord,
+};
+use dashmap::DashMap;
 use deadpool_postgres::Pool;
 use lazy_static::lazy_static;
 use postgres_types::Type;
 use regex::Regex;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc, Mutex, Semaphore};
 
 lazy_static! {
     pub static ref HTTP_CLIENT: reqwest::Client = reqwest::ClientBuilder::new()
@@ -34,7 +41,17 @@ lazy_static! {
     pub static ref IS_UPDATING: Mutex<bool> = Mutex::new(false);
     pub static ref TOTAL_UPDATES: Mutex<i32> = Mutex::new(0);
     pub static ref LAST_UPDATED: Mutex<i64> = Mutex::new(0);
-    pub static ref WEBHOOK: Mutex<Option<Webhook>> = Mutex::new(None);
+    pub static ref NOTIFICATION_QUEUE: std::sync::Mutex<Option<mpsc::UnboundedSender<Notification>>> =
+        std::sync::Mutex::new(None);
     pub static ref BID_ARRAY: Mutex<Option<Type>> = Mutex::new(None);
     pub static ref DATABASE: Mutex<Option<Pool>> = Mutex::new(None);
+    pub static ref SNAPSHOT: Mutex<Option<PathBuf>> = Mutex::new(None);
+    pub static ref STREAM: broadcast::Sender<StreamEvent> = broadcast::channel(4096).0;
+    /* Permits are added on startup from the config */
+    pub static ref RAW_QUERY_PERMITS: Semaphore = Semaphore::new(0);
+    pub static ref API_KEY_CACHE: DashMap<String, (Option<ApiKey>, Instant)> = DashMap::new();
+    pub static ref RATE_LIMIT_USAGE: DashMap<String, Usage> = DashMap::new();
+    pub static ref USAGE_QUEUE: std::sync::Mutex<Option<mpsc::UnboundedSender<UsageRecord>>> =
+        std::sync::Mutex::new(None);
+    pub static ref METRICS: Metrics = Metrics::new();
 }
@@ -0,0 +1,95 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Server-sent events of auctions as `update_auctions` processes them
+
+use crate::statics::STREAM;
+use serde_json::Value;
+use tokio::sync::broadcast::Receiver;
+
+/// An event sent to stream subscribers. The filter fields are not sent, only `data` is
+#[derive(Clone)]
+pub struct StreamEvent {
+    /// Name of the event (auction, underbin, or ended_auction)
+    pub event: &'static str,
+    pub item_id: String,
+    pub internal_id: String,
+    /// Unknown for ended auctions
+    pub tier: Option<String>,
+    pub bin: bool,
+    pub price: i64,
+    pub data: Value,
+}
+
+impl StreamEvent {
+    /// Formats the event as a server-sent event
+    pub fn to_sse(&self) -> String {
+        format!("event: {}\ndata: {}\n\n", self.event, self.data)
+    }
+}
+
+/// Query parameters a subscriber can filter events by. Empty filters match everything
+#[derive(Default)]
+pub struct StreamFilter {
+    pub events: Vec<String>,
+    /// Matches either the item id or internal id
+    pub item_ids: Vec<String>,
+    pub tier: Option<String>,
+    pub bin: Option<bool>,
+    pub max_price: Option<i64>,
+}
+
+impl StreamFilter {
+    pub fn matches(&self, event: &StreamEvent) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|e| e == event.event))
+            && (self.item_ids.is_empty()
+                || self
+                    .item_ids
+                    .iter()
+                    .any(|id| id == &event.item_id || id == &event.internal_id))
+            && self
+                .tier
+                .as_ref()
+                .is_none_or(|tier| event.tier.as_ref().is_some_and(|t| t == tier))
+            && self.bin.is_none_or(|bin| bin == event.bin)
+            && self
+                .max_price
+                .is_none_or(|max_price| event.price <= max_price)
+    }
+}
+
+/// If anyone is subscribed, so events don't need to be built otherwise
+pub fn has_subscribers() -> bool {
+    STREAM.receiver_count() > 0
+}
+
+/// Subscribes to events, unless there are already the most subscribers allowed
+pub fn subscribe(max_subscribers: usize) -> Option<Receiver<StreamEvent>> {
+    let receiver = STREAM.subscribe();
+    // Checked after subscribing so two requests at once can't both take the last place
+    if STREAM.receiver_count() > max_subscribers {
+        return None;
+    }
+
+    Some(receiver)
+}
+
+pub fn publish(event: StreamEvent) {
+    // Only fails if nobody is subscribed
+    let _ = STREAM.send(event);
+}
@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::utils::{is_false, median};
+use crate::{
+    error::QueryApiError,
+    utils::{is_false, median, percentile},
+};
 use dashmap::DashMap;
 use postgres_types::{FromSql, ToSql};
 use serde::{Deserialize, Serialize};
@@ -91,6 +94,8 @@ pub struct QueryDatabaseItem {
     pub necron_scrolls: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gemstones: Option<Vec<String>>,
+    #[serde(skip_serializing)]
+    pub pet_level_id: Option<String>,
 }
 
 impl From<Row> for QueryDatabaseItem {
@@ -134,6 +139,7 @@ impl From<Row> for QueryDatabaseItem {
             etherwarp: row.get("etherwarp"),
             necron_scrolls: row.get("necron_scrolls"),
             gemstones: row.get("gemstones"),
+            pet_level_id: row.try_get("pet_level_id").unwrap_or(None),
         }
     }
 }
@@ -165,6 +171,23 @@ impl AverageDatabaseItem {
         median(&combined_vec)
     }
 
+    pub fn get_min(&self) -> f32 {
+        self.prices.iter().map(|e| e.price).fold(f32::MAX, f32::min)
+    }
+
+    pub fn get_max(&self) -> f32 {
+        self.prices.iter().map(|e| e.price).fold(f32::MIN, f32::max)
+    }
+
+    pub fn get_weighted_average(&self) -> f32 {
+        let sales = self.prices.iter().map(|e| e.sales).sum::<f32>();
+        if sales <= 0.0 {
+            return self.get_average();
+        }
+
+        self.prices.iter().map(|e| e.price * e.sales).sum::<f32>() / sales
+    }
+
     pub fn get_modified_median(&self, percent: f32) -> f32 {
         let combined_vec: Vec<f32> = self.prices.iter().map(|e| e.price).collect();
 
@@ -188,6 +211,69 @@ impl AverageDatabaseItem {
             sum / count as f32
         }
     }
+
+    pub fn get_percentile(&self, percentile_rank: f32) -> f32 {
+        let combined_vec: Vec<f32> = self.prices.iter().map(|e| e.price).collect();
+        percentile(&combined_vec, percentile_rank)
+    }
+
+    /// Mean after removing the lowest and highest `percent` of prices
+    pub fn get_trimmed_mean(&self, percent: f32) -> f32 {
+        let mut combined_vec: Vec<f32> = self.prices.iter().map(|e| e.price).collect();
+        combined_vec.sort_by(|a, b| a.total_cmp(b));
+
+        let trim = (combined_vec.len() as f32 * percent) as usize;
+        let trimmed = &combined_vec[trim..combined_vec.len() - trim];
+        if trimmed.is_empty() {
+            median(&combined_vec)
+        } else {
+            trimmed.iter().sum::<f32>() / trimmed.len() as f32
+        }
+    }
+
+    /// Mean of prices within `threshold` interquartile ranges of the first and third quartiles
+    pub fn get_iqr_mean(&self, threshold: f32) -> f32 {
+        let q1 = self.get_percentile(25.0);
+        let q3 = self.get_percentile(75.0);
+        let iqr = q3 - q1;
+
+        self.get_bounded_mean(q1 - threshold * iqr, q3 + threshold * iqr)
+    }
+
+    /// Mean of prices within `threshold` scaled median absolute deviations of the median
+    pub fn get_mad_mean(&self, threshold: f32) -> f32 {
+        let combined_vec: Vec<f32> = self.prices.iter().map(|e| e.price).collect();
+        let median_price = median(&combined_vec);
+        let deviations: Vec<f32> = combined_vec
+            .iter()
+            .map(|e| (e - median_price).abs())
+            .collect();
+        // Scaled so the deviation is comparable to a standard deviation for normal data
+        let mad = 1.4826 * median(&deviations);
+
+        self.get_bounded_mean(
+            median_price - threshold * mad,
+            median_price + threshold * mad,
+        )
+    }
+
+    fn get_bounded_mean(&self, lower_bound: f32, upper_bound: f32) -> f32 {
+        let mut sum = 0.0;
+        let mut count = 0;
+
+        for ele in &self.prices {
+            if lower_bound <= ele.price && ele.price <= upper_bound {
+                sum += ele.price;
+                count += 1
+            }
+        }
+
+        if count == 0 {
+            self.get_median()
+        } else {
+            sum / count as f32
+        }
+    }
 }
 
 impl From<Row> for AverageDatabaseItem {
@@ -229,6 +315,56 @@ impl AvgSum {
     }
 }
 
+/* Average History API */
+#[derive(Serialize)]
+pub struct AverageHistoryItem {
+    pub time: i32,
+    pub mean: f32,
+    pub median: f32,
+    pub min: f32,
+    pub max: f32,
+    pub sales: f32,
+}
+
+impl AverageHistoryItem {
+    /// Groups the average prices of each minute (with their time in seconds) into steps of
+    /// `step_secs`, ordered by time. The mean is weighted by the sales of each minute
+    pub fn from_prices(prices: Vec<(i32, AvgAh)>, step_secs: i32) -> Vec<AverageHistoryItem> {
+        let mut buckets: BTreeMap<i32, Vec<AvgAh>> = BTreeMap::new();
+        for (time, price) in prices {
+            buckets
+                .entry(time - time.rem_euclid(step_secs))
+                .or_default()
+                .push(price);
+        }
+
+        buckets
+            .into_iter()
+            .map(|(time, prices)| {
+                let bucket = AverageDatabaseItem {
+                    item_id: String::new(),
+                    prices,
+                };
+                AverageHistoryItem {
+                    time,
+                    mean: bucket.get_weighted_average(),
+                    median: bucket.get_median(),
+                    min: bucket.get_min(),
+                    max: bucket.get_max(),
+                    sales: bucket.get_sales(1.0),
+                }
+            })
+            .collect()
+    }
+}
+
+/* Lowest Bin History API */
+#[derive(Serialize)]
+pub struct LowestBinHistoryItem {
+    pub time: i32,
+    pub price: f32,
+}
+
 /* Pets API */
 #[derive(Serialize)]
 pub struct PetsDatabaseItem {
@@ -303,6 +439,11 @@ pub struct PartialExtraAttr {
 }
 
 impl PartialExtraAttr {
+    pub fn get_pet_info(&self) -> Result<PetInfo, QueryApiError> {
+        serde_json::from_str(self.pet.as_ref().ok_or(QueryApiError::MissingPetInfo)?)
+            .map_err(QueryApiError::InvalidPetInfo)
+    }
+
     pub fn is_shiny(&self) -> bool {
         if let Some(is_shiny_value) = &self.is_shiny {
             return is_shiny_value == &1;
@@ -464,6 +605,8 @@ pub struct Auction {
     pub item_name: String,
     pub item_lore: String,
     pub tier: String,
+    #[serde(default)]
+    pub category: String,
     pub starting_bid: i64,
     pub highest_bid_amount: i64,
     pub item_bytes: String,
@@ -0,0 +1,240 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{statics::MC_CODE_REGEX, structs::AverageDatabaseItem, utils::calculate_with_taxes};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+
+/// Rules deciding which new bins are listed as under bins. Loaded from the JSON file at `UNDERBIN_RULES`
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct UnderbinRules {
+    /// Minimum profit after taxes
+    pub min_profit: f32,
+    /// Minimum profit after taxes as a percent of the bin price
+    pub min_profit_percent: f32,
+    /// Bins more expensive than this are ignored
+    pub max_price: Option<f32>,
+    /// Minimum confidence (0 to 1) that the item resells for the expected price
+    pub min_confidence: f32,
+    /// How many hours of average bins are used to estimate the resale price and liquidity
+    pub average_hours: i32,
+    /// Sales per day at which liquidity halves the confidence. More sales bring it closer to full confidence
+    pub liquid_sales: f32,
+    /// If not empty, only these internal ids (or ids matching include_regex) are considered
+    pub include_ids: Vec<String>,
+    #[serde(deserialize_with = "deserialize_regexes")]
+    pub include_regex: Vec<Regex>,
+    pub exclude_ids: Vec<String>,
+    #[serde(deserialize_with = "deserialize_regexes")]
+    pub exclude_regex: Vec<Regex>,
+    /// Matched against the item name without color codes
+    #[serde(deserialize_with = "deserialize_regexes")]
+    pub exclude_name_regex: Vec<Regex>,
+    /// Matched against the item lore
+    #[serde(deserialize_with = "deserialize_regexes")]
+    pub exclude_lore_regex: Vec<Regex>,
+    /// Replace the profit and price limits for some items. The first matching override is used
+    pub overrides: Vec<UnderbinOverride>,
+}
+
+impl Default for UnderbinRules {
+    fn default() -> Self {
+        Self {
+            min_profit: 1000000.0,
+            min_profit_percent: 0.0,
+            max_price: None,
+            min_confidence: 0.0,
+            average_hours: 24,
+            liquid_sales: 5.0,
+            include_ids: Vec::new(),
+            include_regex: Vec::new(),
+            exclude_ids: Vec::new(),
+            exclude_regex: Vec::new(),
+            exclude_name_regex: vec![
+                Regex::new("^null$").unwrap(),
+                Regex::new("Minion Skin").unwrap(),
+            ],
+            exclude_lore_regex: vec![Regex::new("Furniture").unwrap()],
+            overrides: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct UnderbinOverride {
+    pub ids: Vec<String>,
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub regex: Option<Regex>,
+    /// Auction category (e.g. weapon, armor, accessories, consumables, blocks, misc)
+    pub category: Option<String>,
+    pub min_profit: Option<f32>,
+    pub min_profit_percent: Option<f32>,
+    pub max_price: Option<f32>,
+    pub min_confidence: Option<f32>,
+}
+
+impl UnderbinOverride {
+    fn matches(&self, candidate: &UnderbinCandidate) -> bool {
+        self.ids.iter().any(|id| id == candidate.id)
+            || self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(candidate.id))
+            || self
+                .category
+                .as_ref()
+                .is_some_and(|category| category.eq_ignore_ascii_case(candidate.category))
+    }
+}
+
+/// A new bin that is cheaper than the price it is compared against
+pub struct UnderbinCandidate<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub lore: &'a str,
+    pub category: &'a str,
+    pub price: f32,
+    pub profit: f32,
+    pub confidence: f32,
+}
+
+/// How much a new bin is expected to resell for and how sure we are of it
+#[derive(Debug, PartialEq)]
+pub struct UnderbinScore {
+    /// Lower of the previous lowest bin and the recent median bin price
+    pub expected_resale_price: f32,
+    /// Profit after taxes when reselling at the expected price
+    pub profit: f32,
+    /// Average bin sales per day
+    pub liquidity: f32,
+    /// From 0 to 1, based on how close the lowest bin and median are and how often the item sells
+    pub confidence: f32,
+}
+
+impl UnderbinScore {
+    pub fn new(
+        price: f32,
+        past_bin_price: f32,
+        recent_bins: Option<&AverageDatabaseItem>,
+        rules: &UnderbinRules,
+    ) -> Self {
+        let (expected_resale_price, liquidity, confidence) = match recent_bins {
+            Some(recent_bins) if !recent_bins.prices.is_empty() => {
+                // A previous lowest bin far above what the item sells for is likely a mislist
+                let median = recent_bins.get_median();
+                let agreement = past_bin_price.min(median) / past_bin_price.max(median);
+                let liquidity = recent_bins.get_sales(rules.average_hours as f32 / 24.0);
+
+                (
+                    past_bin_price.min(median),
+                    liquidity,
+                    agreement * liquidity / (liquidity + rules.liquid_sales),
+                )
+            }
+            _ => (past_bin_price, 0.0, 0.0),
+        };
+
+        Self {
+            expected_resale_price,
+            profit: calculate_with_taxes(expected_resale_price) - price,
+            liquidity,
+            confidence,
+        }
+    }
+}
+
+impl UnderbinRules {
+    pub fn load_or_panic(path: &str) -> Self {
+        if path.is_empty() {
+            return Self::default();
+        }
+
+        let file = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Unable to read underbin rules {}: {}", path, e));
+        serde_json::from_str(&file)
+            .unwrap_or_else(|e| panic!("Unable to parse underbin rules {}: {}", path, e))
+    }
+
+    /// Checks the include and exclude lists, before the profit is known
+    pub fn is_allowed(&self, id: &str, name: &str, lore: &str) -> bool {
+        if (!self.include_ids.is_empty() || !self.include_regex.is_empty())
+            && !self.include_ids.iter().any(|include| include == id)
+            && !self.include_regex.iter().any(|regex| regex.is_match(id))
+        {
+            return false;
+        }
+
+        let name = MC_CODE_REGEX.replace_all(name, "");
+        !(self.exclude_ids.iter().any(|exclude| exclude == id)
+            || self.exclude_regex.iter().any(|regex| regex.is_match(id))
+            || self
+                .exclude_name_regex
+                .iter()
+                .any(|regex| regex.is_match(&name))
+            || self
+                .exclude_lore_regex
+                .iter()
+                .any(|regex| regex.is_match(lore)))
+    }
+
+    pub fn is_underbin(&self, candidate: &UnderbinCandidate) -> bool {
+        if !self.is_allowed(candidate.id, candidate.name, candidate.lore) {
+            return false;
+        }
+
+        let item_override = self
+            .overrides
+            .iter()
+            .find(|item_override| item_override.matches(candidate));
+        let min_profit = item_override
+            .and_then(|item_override| item_override.min_profit)
+            .unwrap_or(self.min_profit);
+        let min_profit_percent = item_override
+            .and_then(|item_override| item_override.min_profit_percent)
+            .unwrap_or(self.min_profit_percent);
+        let max_price = item_override
+            .and_then(|item_override| item_override.max_price)
+            .or(self.max_price);
+        let min_confidence = item_override
+            .and_then(|item_override| item_override.min_confidence)
+            .unwrap_or(self.min_confidence);
+
+        candidate.profit > min_profit
+            && candidate.profit >= candidate.price * min_profit_percent / 100.0
+            && max_price.is_none_or(|max_price| candidate.price <= max_price)
+            && candidate.confidence >= min_confidence
+    }
+}
+
+fn deserialize_regexes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|regex| Regex::new(regex).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|regex| Regex::new(&regex).map_err(serde::de::Error::custom))
+        .transpose()
+}
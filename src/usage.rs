@@ -0,0 +1,347 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Aggregated API usage of each key and endpoint, stored in the database for the usage report
+
+use crate::{
+    config::Config,
+    statics::{API_KEY_CACHE, USAGE_QUEUE},
+    utils::{get_client, get_timestamp_millis},
+};
+use log::error;
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+use tokio::{sync::mpsc, time::interval};
+use tokio_postgres::Error;
+
+/// Upper bounds of the latency histogram buckets in milliseconds, the last bucket has no bound
+pub const LATENCY_BUCKETS_MS: [f64; 20] = [
+    1.0, 2.0, 3.0, 5.0, 7.5, 10.0, 15.0, 25.0, 50.0, 75.0, 100.0, 150.0, 250.0, 500.0, 750.0,
+    1000.0, 2500.0, 5000.0, 10000.0, 30000.0,
+];
+/// How often usage is written to the database
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const MINUTE_MILLIS: i64 = 60000;
+const DAY_MILLIS: i64 = 86400000;
+
+/// A finished request
+pub struct UsageRecord {
+    /// Epoch milliseconds the request was received
+    pub time: i64,
+    pub key: String,
+    pub endpoint: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub response_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UsageBucket {
+    /// Epoch milliseconds of the start of the minute
+    time: i64,
+    key: String,
+    endpoint: String,
+    status: i16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageStats {
+    pub requests: i64,
+    pub total_latency_ms: f64,
+    pub max_latency_ms: f64,
+    pub response_bytes: i64,
+    /// Requests in each of the LATENCY_BUCKETS_MS, plus one for slower requests
+    pub latency_buckets: Vec<i64>,
+}
+
+impl Default for UsageStats {
+    fn default() -> Self {
+        Self {
+            requests: 0,
+            total_latency_ms: 0.0,
+            max_latency_ms: 0.0,
+            response_bytes: 0,
+            latency_buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+}
+
+impl UsageStats {
+    pub fn record(&mut self, latency_ms: f64, response_bytes: u64) {
+        self.requests += 1;
+        self.total_latency_ms += latency_ms;
+        self.max_latency_ms = self.max_latency_ms.max(latency_ms);
+        self.response_bytes += response_bytes as i64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| latency_ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.latency_buckets[bucket] += 1;
+    }
+
+    pub fn merge(&mut self, other: &UsageStats) {
+        self.requests += other.requests;
+        self.total_latency_ms += other.total_latency_ms;
+        self.max_latency_ms = self.max_latency_ms.max(other.max_latency_ms);
+        self.response_bytes += other.response_bytes;
+        for (bucket, requests) in self.latency_buckets.iter_mut().zip(&other.latency_buckets) {
+            *bucket += requests;
+        }
+    }
+
+    /// Estimates the latency percentile (0 to 100) as the upper bound of the bucket it falls in
+    pub fn percentile(&self, percentile: f64) -> f64 {
+        let total = self.latency_buckets.iter().sum::<i64>();
+        if total == 0 {
+            return 0.0;
+        }
+
+        let target = (total as f64 * percentile / 100.0).ceil().max(1.0) as i64;
+        let mut seen = 0;
+        for (i, requests) in self.latency_buckets.iter().enumerate() {
+            seen += requests;
+            if seen >= target {
+                return LATENCY_BUCKETS_MS
+                    .get(i)
+                    .map_or(self.max_latency_ms, |bound| bound.min(self.max_latency_ms));
+            }
+        }
+
+        self.max_latency_ms
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyReport {
+    pub average: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub key: String,
+    pub endpoint: String,
+    pub requests: i64,
+    /// Requests by response status
+    pub statuses: BTreeMap<String, i64>,
+    pub response_bytes: i64,
+    pub latency_ms: LatencyReport,
+}
+
+impl UsageReport {
+    pub fn new(
+        key: String,
+        endpoint: String,
+        statuses: BTreeMap<String, i64>,
+        stats: &UsageStats,
+    ) -> Self {
+        Self {
+            key,
+            endpoint,
+            requests: stats.requests,
+            statuses,
+            response_bytes: stats.response_bytes,
+            latency_ms: LatencyReport {
+                average: if stats.requests > 0 {
+                    stats.total_latency_ms / stats.requests as f64
+                } else {
+                    0.0
+                },
+                p50: stats.percentile(50.0),
+                p90: stats.percentile(90.0),
+                p99: stats.percentile(99.0),
+                max: stats.max_latency_ms,
+            },
+        }
+    }
+}
+
+/// Name usage of a key is recorded under. Keys from the database are recorded by their prefix,
+/// and the full key is never stored
+pub fn get_key_name(config: &Config, key: &str) -> String {
+    if key.is_empty() {
+        String::from("anonymous")
+    } else if key == config.admin_api_key {
+        String::from("admin")
+    } else if key == config.api_key {
+        String::from("api_key")
+    } else {
+        // The handler looked up the key if it needed one
+        API_KEY_CACHE
+            .get(key)
+            .and_then(|cached| cached.0.as_ref().map(|api_key| api_key.key_prefix.clone()))
+            .unwrap_or_else(|| String::from("unknown"))
+    }
+}
+
+/// Sends a request to be aggregated. Does nothing if usage is not being recorded
+pub fn record_usage(record: UsageRecord) {
+    if let Some(queue) = USAGE_QUEUE.lock().unwrap().as_ref() {
+        let _ = queue.send(record);
+    }
+}
+
+/// Aggregates recorded requests by minute and writes them to the database every minute. Usage
+/// older than the retention days is deleted, or kept forever if the retention is not positive
+pub fn start_usage_recorder(retention_days: i64) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<UsageRecord>();
+    let _ = USAGE_QUEUE.lock().unwrap().insert(sender);
+
+    tokio::spawn(async move {
+        let mut usage = HashMap::new();
+        let mut flush = interval(FLUSH_INTERVAL);
+
+        loop {
+            tokio::select! {
+                record = receiver.recv() => {
+                    let Some(record) = record else {
+                        break;
+                    };
+                    usage
+                        .entry(UsageBucket {
+                            time: record.time - record.time % MINUTE_MILLIS,
+                            key: record.key,
+                            endpoint: record.endpoint,
+                            status: record.status as i16,
+                        })
+                        .or_insert_with(UsageStats::default)
+                        .record(record.latency_ms, record.response_bytes);
+                }
+                _ = flush.tick() => {
+                    if retention_days > 0 {
+                        if let Err(e) = delete_usage_before(
+                            get_timestamp_millis() as i64 - retention_days * DAY_MILLIS,
+                        )
+                        .await
+                        {
+                            error!("Error deleting old API usage: {}", e);
+                        }
+                    }
+                    if usage.is_empty() {
+                        continue;
+                    }
+                    match write_usage(&usage).await {
+                        Ok(_) => usage.clear(),
+                        // Kept to retry on the next flush
+                        Err(e) => error!("Error writing API usage: {}", e),
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn delete_usage_before(time: i64) -> Result<u64, Error> {
+    get_client()
+        .await
+        .execute("DELETE FROM api_usage WHERE time_t < $1", &[&time])
+        .await
+}
+
+async fn write_usage(usage: &HashMap<UsageBucket, UsageStats>) -> Result<(), Error> {
+    let mut client = get_client().await;
+    let transaction = client.transaction().await?;
+    let statement = transaction
+        .prepare(
+            "INSERT INTO api_usage (time_t, key_name, endpoint, status, requests, total_latency_ms, max_latency_ms, response_bytes, latency_buckets)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .await?;
+    for (bucket, stats) in usage {
+        transaction
+            .execute(
+                &statement,
+                &[
+                    &bucket.time,
+                    &bucket.key,
+                    &bucket.endpoint,
+                    &bucket.status,
+                    &stats.requests,
+                    &stats.total_latency_ms,
+                    &stats.max_latency_ms,
+                    &stats.response_bytes,
+                    &stats.latency_buckets,
+                ],
+            )
+            .await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Usage of each key and endpoint between two epoch timestamps in milliseconds, with the most
+/// used first. Usage is stored by minute and written once a minute, so the last minute is missing
+pub async fn get_usage_report(from: i64, to: i64) -> Result<Vec<UsageReport>, Error> {
+    let client = get_client().await;
+    let mut usage: BTreeMap<(String, String), (BTreeMap<String, i64>, UsageStats)> =
+        BTreeMap::new();
+
+    for row in client
+        .query(
+            "SELECT key_name, endpoint, status, SUM(requests)::BIGINT AS requests, SUM(total_latency_ms) AS total_latency_ms,
+                MAX(max_latency_ms) AS max_latency_ms, SUM(response_bytes)::BIGINT AS response_bytes
+            FROM api_usage WHERE time_t >= $1 AND time_t < $2 GROUP BY key_name, endpoint, status",
+            &[&from, &to],
+        )
+        .await?
+    {
+        let (statuses, stats) = usage
+            .entry((row.get("key_name"), row.get("endpoint")))
+            .or_default();
+        let requests: i64 = row.get("requests");
+        statuses.insert(row.get::<_, i16>("status").to_string(), requests);
+        stats.requests += requests;
+        stats.total_latency_ms += row.get::<_, f64>("total_latency_ms");
+        stats.max_latency_ms = stats.max_latency_ms.max(row.get("max_latency_ms"));
+        stats.response_bytes += row.get::<_, i64>("response_bytes");
+    }
+
+    for row in client
+        .query(
+            "SELECT key_name, endpoint, bucket - 1 AS bucket, SUM(bucket_requests)::BIGINT AS requests
+            FROM api_usage, unnest(latency_buckets) WITH ORDINALITY AS buckets(bucket_requests, bucket)
+            WHERE time_t >= $1 AND time_t < $2 GROUP BY key_name, endpoint, bucket",
+            &[&from, &to],
+        )
+        .await?
+    {
+        let bucket = row.get::<_, i64>("bucket") as usize;
+        if let Some((_, stats)) = usage.get_mut(&(row.get("key_name"), row.get("endpoint"))) {
+            if let Some(requests) = stats.latency_buckets.get_mut(bucket) {
+                *requests += row.get::<_, i64>("requests");
+            }
+        }
+    }
+
+    let mut report = usage
+        .into_iter()
+        .map(|((key, endpoint), (statuses, stats))| {
+            UsageReport::new(key, endpoint, statuses, &stats)
+        })
+        .collect::<Vec<UsageReport>>();
+    report.sort_by_key(|usage| Reverse(usage.requests));
+
+    Ok(report)
+}
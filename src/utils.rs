@@ -16,7 +16,15 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::{config::Config, statics::*, structs::*};
+use crate::{
+    api_keys::{get_api_key, Scope},
+    config::Config,
+    error::QueryApiError,
+    item_key::is_pet_level_id,
+    notifier::{Notification, Severity},
+    statics::*,
+    structs::*,
+};
 use base64::{engine::general_purpose, Engine};
 use dashmap::{DashMap, DashSet};
 use deadpool_postgres::Client;
@@ -27,7 +35,6 @@ use serde_json::Value;
 use std::{
     cmp::Ordering,
     fmt::Write,
-    fs::OpenOptions,
     sync::{Arc, Mutex},
     thread,
     time::{Instant, SystemTime, UNIX_EPOCH},
@@ -36,13 +43,13 @@ use tokio::time::{self, Duration};
 use tokio_postgres::{binary_copy::BinaryCopyInWriter, Error};
 
 /* Repeat a task */
-pub async fn start_auction_loop<F, Fut>(mut f: F)
+pub async fn start_auction_loop<F, Fut>(config: Arc<Config>, mut f: F)
 where
     F: Send + 'static + FnMut() -> Fut,
     Fut: Future<Output = ()> + Send + 'static,
 {
     // Create stream of intervals.
-    let mut interval = time::interval(get_duration_until_api_update().await);
+    let mut interval = time::interval(get_duration_until_api_update(&config).await);
     tokio::spawn(async move {
         loop {
             // Skip tick at 0ms
@@ -52,19 +59,22 @@ where
             // Spawn a task for this tick.
             f().await;
             // Updated to new interval
-            interval = time::interval(get_duration_until_api_update().await);
+            interval = time::interval(get_duration_until_api_update(&config).await);
         }
     });
 }
 
 /* Gets the time until the next API update according to Cloudflare headers */
-async fn get_duration_until_api_update() -> Duration {
+async fn get_duration_until_api_update(config: &Config) -> Duration {
     let mut num_attempts = 0;
     loop {
         num_attempts += 1;
 
         if let Ok(res) = HTTP_CLIENT
-            .get("https://api.hypixel.net/skyblock/auctions?page=0")
+            .get(format!(
+                "{}/skyblock/auctions?page=0",
+                config.hypixel_api_url
+            ))
             .send()
             .await
         {
@@ -110,41 +120,43 @@ pub fn info(desc: String) {
 
 pub fn info_mention(desc: String, mention: bool) {
     info!("{}", desc);
-    tokio::spawn(async move {
-        if let Some(webhook) = WEBHOOK.lock().await.as_ref() {
-            let _ = webhook
-                .send(|message| {
-                    message.mention(mention).embed(|embed| {
-                        embed
-                            .title("Information")
-                            .color(0x00FFFF)
-                            .description(&desc)
-                    })
-                })
-                .await;
-        }
+    notify(Notification {
+        severity: Severity::Info,
+        title: String::from("Information"),
+        description: desc,
+        mention,
     });
 }
 
 /* Log and send an error message to the Discord webhook */
 pub fn error(desc: String) {
     error!("{}", desc);
-    tokio::spawn(async move {
-        if let Some(webhook) = WEBHOOK.lock().await.as_ref() {
-            let _ = webhook
-                .send(|message| {
-                    message.embed(|embed| embed.title("Error").color(0xFF0000).description(&desc))
-                })
-                .await;
-        }
+    notify(Notification {
+        severity: Severity::Error,
+        title: String::from("Error"),
+        description: desc,
+        mention: false,
     });
 }
 
-pub fn parse_nbt(data: &str) -> Option<PartialNbt> {
+/* Queues a notification to be sent by every notifier that accepts its severity */
+fn notify(notification: Notification) {
+    if let Some(queue) = NOTIFICATION_QUEUE.lock().unwrap().as_ref() {
+        let _ = queue.send(notification);
+    }
+}
+
+/* Parses the first item of base64 encoded, gzipped item bytes */
+pub fn parse_nbt(data: &str) -> Result<PartialNbtElement, QueryApiError> {
     general_purpose::STANDARD
         .decode(data)
         .ok()
         .and_then(|bytes| nbt::from_gzip_reader::<_, PartialNbt>(std::io::Cursor::new(bytes)).ok())
+        .ok_or(QueryApiError::InvalidItemBytes)?
+        .i
+        .into_iter()
+        .next()
+        .ok_or(QueryApiError::MissingItem)
 }
 
 pub fn calculate_with_taxes(price: f32) -> f32 {
@@ -167,14 +179,35 @@ pub fn calculate_with_taxes(price: f32) -> f32 {
     price * (1.0 - tax)
 }
 
-pub fn valid_api_key(config: Arc<Config>, key: String, admin_only: bool) -> bool {
+/* Checks the key against the API_KEY and ADMIN_API_KEY, then against the keys in the database */
+pub async fn valid_api_key(config: Arc<Config>, key: String, scope: Scope) -> bool {
     if config.admin_api_key == key {
         return true;
     }
-    if admin_only {
+    if !scope.is_admin_only() && (config.api_key.is_empty() || key == config.api_key) {
+        return true;
+    }
+    if key.is_empty() {
+        return false;
+    }
+
+    get_api_key(&key)
+        .await
+        .is_some_and(|api_key| api_key.allows(scope, get_timestamp_millis() as i64))
+}
+
+/* Checks if the key is the API_KEY, ADMIN_API_KEY, or an active key in the database (whatever its scopes) */
+pub async fn is_known_api_key(config: &Config, key: &str) -> bool {
+    if key.is_empty() {
         return false;
     }
-    config.api_key.is_empty() || (key == config.api_key)
+    if key == config.admin_api_key || key == config.api_key {
+        return true;
+    }
+
+    get_api_key(key)
+        .await
+        .is_some_and(|api_key| api_key.is_active(get_timestamp_millis() as i64))
 }
 
 pub fn update_lower_else_insert(id: &str, starting_bid: f32, prices: &DashMap<String, f32>) {
@@ -196,6 +229,7 @@ pub async fn update_query_bin_underbin_fn(
     last_updated: i64,
     update_underbin: bool,
     under_bin_prices: &DashMap<String, Value>,
+    time_t: i64,
 ) -> (String, String) {
     let mut ok_logs = String::new();
     let mut err_logs = String::new();
@@ -211,36 +245,64 @@ pub async fn update_query_bin_underbin_fn(
     )
     .await
     {
-        Ok(rows) => write!(
-            ok_logs,
-            "\nSuccessfully inserted {} query auctions into database in {}ms",
-            rows,
-            query_started.elapsed().as_millis()
-        ),
+        Ok(rows) => {
+            METRICS.auctions_inserted.inc_by(rows);
+            write!(
+                ok_logs,
+                "\nSuccessfully inserted {} query auctions into database in {}ms",
+                rows,
+                query_started.elapsed().as_millis()
+            )
+        }
         Err(e) => write!(err_logs, "\nError inserting query into database: {}", e),
     };
 
     if update_lowestbin {
         let bins_started = Instant::now();
-        let _ = match update_bins_local(bin_prices).await {
-            Ok(_) => write!(
+        let _ = match update_bins_database(bin_prices).await {
+            Ok(rows) => write!(
                 ok_logs,
-                "\nSuccessfully updated bins file in {}ms",
+                "\nSuccessfully inserted {} lowest bins into database in {}ms",
+                rows,
                 bins_started.elapsed().as_millis()
             ),
-            Err(e) => write!(err_logs, "\nError updating bins file: {}", e),
+            Err(e) => write!(
+                err_logs,
+                "\nError inserting lowest bins into database: {}",
+                e
+            ),
+        };
+
+        let bins_history_started = Instant::now();
+        let _ = match update_bins_history_database(bin_prices, (time_t / 1000) as i32).await {
+            Ok(rows) => write!(
+                ok_logs,
+                "\nSuccessfully inserted {} lowest bin history into database in {}ms",
+                rows,
+                bins_history_started.elapsed().as_millis()
+            ),
+            Err(e) => write!(
+                err_logs,
+                "\nError inserting lowest bin history into database: {}",
+                e
+            ),
         };
 
         if update_underbin {
             let under_bins_started = Instant::now();
-            let _ = match update_under_bins_local(under_bin_prices).await {
-                Ok(_) => write!(
+            let _ = match update_under_bins_database(under_bin_prices).await {
+                Ok(rows) => write!(
                     ok_logs,
-                    "\nSuccessfully updated under bins file in {}ms",
+                    "\nSuccessfully inserted {} under bins into database in {}ms",
+                    rows,
                     under_bins_started.elapsed().as_millis()
                 ),
                 Err(e) => {
-                    write!(err_logs, "\nError updating under bins file: {}", e)
+                    write!(
+                        err_logs,
+                        "\nError inserting under bins into database: {}",
+                        e
+                    )
                 }
             };
         }
@@ -310,7 +372,7 @@ async fn update_query_database(
             .iter()
             .map(|o| o.item_name.to_string())
             .collect::<DashSet<String>>();
-        update_query_items_local(query_names);
+        update_query_items_database(query_names).await?;
     } else {
         // Remove ended auctions and duplicate 'new' auctions
         let mut delete_uuids = ended_auction_uuids
@@ -382,6 +444,7 @@ async fn update_query_database(
             Type::BOOL,
             Type::TEXT_ARRAY,
             Type::TEXT_ARRAY,
+            Type::TEXT,
         ],
     );
 
@@ -427,6 +490,7 @@ async fn update_query_database(
             &m.etherwarp,
             &m.necron_scrolls,
             &m.gemstones,
+            &m.pet_level_id,
         ];
 
         copy_writer.as_mut().write(&row).await?;
@@ -440,7 +504,7 @@ async fn update_query_database(
         let mut all_auctions_sql = String::from("SELECT item_name");
         // These fields are only needed to update lowest bin
         if update_lowestbin {
-            all_auctions_sql.push_str(", internal_id, lowestbin_price, bin");
+            all_auctions_sql.push_str(", internal_id, pet_level_id, lowestbin_price, bin");
         }
         all_auctions_sql.push_str(" FROM query");
 
@@ -453,10 +517,13 @@ async fn update_query_database(
                 let internal_id: String = ele.get("internal_id");
                 let lowestbin_price: f32 = ele.get("lowestbin_price");
                 update_lower_else_insert(&internal_id, lowestbin_price, bin_prices);
+                if let Some(pet_level_id) = ele.get::<_, Option<String>>("pet_level_id") {
+                    update_lower_else_insert(&pet_level_id, lowestbin_price, bin_prices);
+                }
             }
         }
 
-        update_query_items_local(query_names);
+        update_query_items_database(query_names).await?;
     }
 
     Ok(rows_added)
@@ -510,7 +577,7 @@ async fn update_avgerage_database(
     time_t: i32, // In seconds
 ) -> Result<u64, Error> {
     let table_str = table.to_string();
-    let database = get_client().await;
+    let mut database = get_client().await;
 
     // Delete averages older than 7 days
     tokio::spawn(async move {
@@ -527,11 +594,18 @@ async fn update_avgerage_database(
             .await;
     });
 
-    // Insert new averages
-    let copy_statement = database
-        .prepare(&format!("COPY {} FROM STDIN BINARY", table))
+    // Copied through a temporary table so a repeated time_t (such as a replayed snapshot) is skipped
+    let transaction = database.transaction().await?;
+    let _ = transaction
+        .simple_query(&format!(
+            "CREATE TEMP TABLE {table}_copy (LIKE {table}) ON COMMIT DROP"
+        ))
         .await?;
-    let copy_sink = database.copy_in(&copy_statement).await?;
+
+    let copy_statement = transaction
+        .prepare(&format!("COPY {}_copy FROM STDIN BINARY", table))
+        .await?;
+    let copy_sink = transaction.copy_in(&copy_statement).await?;
     let copy_writer = BinaryCopyInWriter::new(
         copy_sink,
         &[Type::INT4, Type::TEXT, Type::FLOAT4, Type::FLOAT4],
@@ -550,11 +624,20 @@ async fn update_avgerage_database(
             ])
             .await?;
     }
+    copy_writer.finish().await?;
 
-    copy_writer.finish().await
+    let rows_added = transaction
+        .execute(
+            &format!("INSERT INTO {table} SELECT * FROM {table}_copy ON CONFLICT DO NOTHING"),
+            &[],
+        )
+        .await?;
+    transaction.commit().await?;
+
+    Ok(rows_added)
 }
 
-async fn update_bins_local(bin_prices: &DashMap<String, f32>) -> Result<(), serde_json::Error> {
+async fn update_bins_database(bin_prices: &DashMap<String, f32>) -> Result<u64, Error> {
     // Calculate lowestbin of item (regardless of attributes)
     let additional_prices = DashMap::new();
     for ele in bin_prices {
@@ -570,35 +653,187 @@ async fn update_bins_local(bin_prices: &DashMap<String, f32>) -> Result<(), serd
         bin_prices.insert(ele.0, ele.1);
     }
 
-    let file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("lowestbin.json")
-        .unwrap();
-    serde_json::to_writer(file, bin_prices)
-}
-
-async fn update_under_bins_local(
-    bin_prices: &DashMap<String, Value>,
-) -> Result<(), serde_json::Error> {
-    let file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("underbin.json")
-        .unwrap();
-    serde_json::to_writer(file, &bin_prices)
-}
-
-fn update_query_items_local(query_prices: DashSet<String>) {
-    let file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("query_items.json")
-        .unwrap();
-    let _ = serde_json::to_writer(file, &query_prices);
+    let mut database = get_client().await;
+    // Replace all rows in one transaction so readers never see a partial table
+    let transaction = database.transaction().await?;
+    let mut rows_added = 0;
+    // Pet level ids are only used to score under bins, so they are kept out of the public table
+    for (table, is_pet_level) in [("lowestbin", false), ("pet_lowestbin", true)] {
+        let _ = transaction
+            .simple_query(&format!("DELETE FROM {}", table))
+            .await?;
+
+        let copy_statement = transaction
+            .prepare(&format!("COPY {} FROM STDIN BINARY", table))
+            .await?;
+        let copy_sink = transaction.copy_in(&copy_statement).await?;
+        let copy_writer = BinaryCopyInWriter::new(copy_sink, &[Type::TEXT, Type::FLOAT4]);
+        pin_mut!(copy_writer);
+
+        for ele in bin_prices {
+            if is_pet_level_id(ele.key()) != is_pet_level {
+                continue;
+            }
+
+            copy_writer
+                .as_mut()
+                .write(&[ele.key(), ele.value()])
+                .await?;
+        }
+
+        let table_rows_added = copy_writer.finish().await?;
+        if !is_pet_level {
+            rows_added = table_rows_added;
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(rows_added)
+}
+
+async fn update_bins_history_database(
+    bin_prices: &DashMap<String, f32>,
+    time_t: i32, // In seconds
+) -> Result<u64, Error> {
+    let mut database = get_client().await;
+
+    // Delete lowest bin history older than 7 days
+    tokio::spawn(async move {
+        let _ = get_client()
+            .await
+            .simple_query(&format!(
+                "DELETE FROM lowestbin_history WHERE time_t < {}",
+                time_t - 604800 // 7 days (in seconds)
+            ))
+            .await;
+    });
+
+    // Copied through a temporary table so a repeated time_t (such as a replayed snapshot) is skipped
+    let transaction = database.transaction().await?;
+    let _ = transaction
+        .simple_query(
+            "CREATE TEMP TABLE lowestbin_history_copy (LIKE lowestbin_history) ON COMMIT DROP",
+        )
+        .await?;
+
+    let copy_statement = transaction
+        .prepare("COPY lowestbin_history_copy FROM STDIN BINARY")
+        .await?;
+    let copy_sink = transaction.copy_in(&copy_statement).await?;
+    let copy_writer = BinaryCopyInWriter::new(copy_sink, &[Type::INT4, Type::TEXT, Type::FLOAT4]);
+    pin_mut!(copy_writer);
+
+    for ele in bin_prices {
+        if is_pet_level_id(ele.key()) {
+            continue;
+        }
+
+        copy_writer
+            .as_mut()
+            .write(&[&time_t, ele.key(), ele.value()])
+            .await?;
+    }
+    copy_writer.finish().await?;
+
+    let rows_added = transaction
+        .execute(
+            "INSERT INTO lowestbin_history SELECT * FROM lowestbin_history_copy ON CONFLICT DO NOTHING",
+            &[],
+        )
+        .await?;
+    transaction.commit().await?;
+
+    Ok(rows_added)
+}
+
+async fn update_under_bins_database(
+    under_bin_prices: &DashMap<String, Value>,
+) -> Result<u64, Error> {
+    let mut database = get_client().await;
+    let transaction = database.transaction().await?;
+    let _ = transaction.simple_query("DELETE FROM underbin").await?;
+
+    let copy_statement = transaction
+        .prepare("COPY underbin FROM STDIN BINARY")
+        .await?;
+    let copy_sink = transaction.copy_in(&copy_statement).await?;
+    let copy_writer = BinaryCopyInWriter::new(copy_sink, &[Type::TEXT, Type::JSONB]);
+    pin_mut!(copy_writer);
+
+    for ele in under_bin_prices {
+        copy_writer
+            .as_mut()
+            .write(&[ele.key(), ele.value()])
+            .await?;
+    }
+
+    let rows_added = copy_writer.finish().await?;
+    transaction.commit().await?;
+
+    Ok(rows_added)
+}
+
+async fn update_query_items_database(query_names: DashSet<String>) -> Result<u64, Error> {
+    let mut database = get_client().await;
+    let transaction = database.transaction().await?;
+    let _ = transaction.simple_query("DELETE FROM query_items").await?;
+
+    let copy_statement = transaction
+        .prepare("COPY query_items FROM STDIN BINARY")
+        .await?;
+    let copy_sink = transaction.copy_in(&copy_statement).await?;
+    let copy_writer = BinaryCopyInWriter::new(copy_sink, &[Type::TEXT]);
+    pin_mut!(copy_writer);
+
+    for ele in query_names {
+        copy_writer.as_mut().write(&[&ele]).await?;
+    }
+
+    let rows_added = copy_writer.finish().await?;
+    transaction.commit().await?;
+
+    Ok(rows_added)
+}
+
+/* Gets the lowest bins stored by the previous update */
+pub async fn get_past_bin_prices() -> DashMap<String, f32> {
+    let past_bin_prices = DashMap::new();
+
+    if let Ok(rows) = get_client()
+        .await
+        .query(
+            "SELECT item_id, price FROM lowestbin UNION ALL SELECT item_id, price FROM pet_lowestbin",
+            &[],
+        )
+        .await
+    {
+        for row in rows {
+            past_bin_prices.insert(row.get("item_id"), row.get("price"));
+        }
+    }
+
+    past_bin_prices
+}
+
+/* Gets the bin prices and sales of each item over the past hours */
+pub async fn get_recent_bin_averages(hours: i32) -> DashMap<String, AverageDatabaseItem> {
+    let recent_bin_averages = DashMap::new();
+
+    if let Ok(rows) = get_client()
+        .await
+        .query(
+            "SELECT item_id, ARRAY_AGG((price, sales)::avg_ah) prices FROM (SELECT * FROM average_bin UNION ALL SELECT * FROM pet_average_bin) bins WHERE time_t > $1 GROUP BY item_id",
+            &[&(get_timestamp_secs() - hours * 3600)],
+        )
+        .await
+    {
+        for row in rows {
+            let item = AverageDatabaseItem::from(row);
+            recent_bin_averages.insert(item.item_id.to_string(), item);
+        }
+    }
+
+    recent_bin_averages
 }
 
 pub async fn get_client() -> Client {
@@ -666,6 +901,15 @@ fn partition(data: &[f32]) -> (Vec<f32>, f32, Vec<f32>) {
     (left, pivot, right)
 }
 
+/// Percentile (0 to 100) of the data, interpolating between the closest ranks
+pub fn percentile(data: &[f32], percentile: f32) -> f32 {
+    let rank = percentile / 100.0 * (data.len() - 1) as f32;
+    let lower = select(data, rank.floor() as usize);
+    let upper = select(data, rank.ceil() as usize);
+
+    lower + (upper - lower) * rank.fract()
+}
+
 pub fn update_average_map(map: &DashMap<String, AvgSum>, id: &str, price: i64, count: i16) {
     // If the map already has this id, then add to the existing elements, otherwise create a new entry
     if let Some(mut value) = map.get_mut(id) {
@@ -15,11 +15,21 @@
  * You should have received a copy of the GNU Affero General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
-use crate::statics::HTTP_CLIENT;
+use crate::statics::{HTTP_CLIENT, METRICS};
+use reqwest::{header, Response, StatusCode};
 use serde::Serialize;
-use std::error::Error;
+use serde_json::Value;
+use std::{error::Error, time::Duration};
 
-#[derive(Debug, Serialize)]
+/* Discord limits */
+const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+const EMBEDS_PER_MESSAGE_LIMIT: usize = 10;
+const MESSAGE_EMBED_CHARACTERS_LIMIT: usize = 6000;
+
+/// Attempts to deliver a webhook before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Default, Serialize)]
 pub struct EmbedBuilder {
     title: Option<String>,
     description: Option<String>,
@@ -28,11 +38,7 @@ pub struct EmbedBuilder {
 
 impl EmbedBuilder {
     pub fn new() -> Self {
-        Self {
-            title: None,
-            description: None,
-            color: None,
-        }
+        Self::default()
     }
 
     pub fn title(&mut self, title: &str) -> &mut EmbedBuilder {
@@ -59,14 +65,78 @@ impl EmbedBuilder {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Embed {
     title: Option<String>,
     description: Option<String>,
     color: Option<i32>,
 }
 
-#[derive(Debug, Serialize)]
+impl Embed {
+    fn characters(&self) -> usize {
+        self.title.as_ref().map_or(0, |title| title.chars().count())
+            + self
+                .description
+                .as_ref()
+                .map_or(0, |description| description.chars().count())
+    }
+
+    /// Splits a long description across multiple embeds with the same title and color
+    fn split(self) -> Vec<Embed> {
+        match &self.description {
+            Some(description) if description.chars().count() > EMBED_DESCRIPTION_LIMIT => {
+                split_description(description, EMBED_DESCRIPTION_LIMIT)
+                    .into_iter()
+                    .map(|description| Embed {
+                        title: self.title.clone(),
+                        description: Some(description),
+                        color: self.color,
+                    })
+                    .collect()
+            }
+            _ => vec![self],
+        }
+    }
+}
+
+/* Splits text into parts of at most limit characters, preferring to split between lines */
+fn split_description(description: &str, limit: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for line in description.split('\n') {
+        let line_len = line.chars().count();
+        if !current.is_empty() && current_len + 1 + line_len <= limit {
+            current.push('\n');
+            current.push_str(line);
+            current_len += 1 + line_len;
+            continue;
+        }
+
+        if !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+        }
+
+        // Lines longer than the limit are split wherever they reach it
+        let chars = line.chars().collect::<Vec<char>>();
+        let mut chunks = chars
+            .chunks(limit)
+            .map(|chunk| chunk.iter().collect::<String>())
+            .collect::<Vec<String>>();
+        current = chunks.pop().unwrap_or_default();
+        current_len = current.chars().count();
+        parts.append(&mut chunks);
+    }
+
+    if !current.is_empty() || parts.is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct Message {
     content: Option<String>,
     embeds: Vec<Embed>,
@@ -74,10 +144,7 @@ pub struct Message {
 
 impl Message {
     pub fn new() -> Self {
-        Self {
-            content: None,
-            embeds: vec![],
-        }
+        Self::default()
     }
 
     pub fn content(&mut self, content: &str) -> &mut Message {
@@ -85,11 +152,8 @@ impl Message {
         self
     }
 
-    pub fn mention(&mut self, mention: bool) -> &mut Message {
-        if mention {
-            self.content("<@796791167366594592>");
-        }
-        self
+    pub fn mention(&mut self, mention: &str) -> &mut Message {
+        self.content(mention)
     }
 
     pub fn embed<F>(&mut self, embed: F) -> &mut Message
@@ -99,6 +163,32 @@ impl Message {
         self.embeds.push((embed(&mut EmbedBuilder::new())).build());
         self
     }
+
+    /// Splits the message into messages within Discord's embed limits. Only the first keeps the content
+    pub fn split(&self) -> Vec<Message> {
+        let mut messages = vec![Message {
+            content: self.content.clone(),
+            embeds: Vec::new(),
+        }];
+        let mut characters = 0;
+
+        for embed in self.embeds.iter().cloned().flat_map(Embed::split) {
+            let embed_characters = embed.characters();
+            let current = messages.last().unwrap();
+            if !current.embeds.is_empty()
+                && (current.embeds.len() >= EMBEDS_PER_MESSAGE_LIMIT
+                    || characters + embed_characters > MESSAGE_EMBED_CHARACTERS_LIMIT)
+            {
+                messages.push(Message::new());
+                characters = 0;
+            }
+
+            characters += embed_characters;
+            messages.last_mut().unwrap().embeds.push(embed);
+        }
+
+        messages
+    }
 }
 
 pub struct Webhook {
@@ -112,13 +202,97 @@ impl Webhook {
         }
     }
 
-    pub async fn send<F>(&self, t: F) -> Result<(), Box<dyn Error>>
+    pub async fn send<F>(&self, t: F) -> Result<(), Box<dyn Error + Send + Sync>>
     where
         F: Fn(&mut Message) -> &mut Message,
     {
         let mut msg = Message::new();
         let message = t(&mut msg);
-        HTTP_CLIENT.post(&self.url).json(&message).send().await?;
+        for part in message.split() {
+            post_json(&self.url, &part).await?;
+        }
         Ok(())
     }
+
+    /// Posts any JSON body instead of a Discord message
+    pub async fn send_json<T>(&self, json: &T) -> Result<(), Box<dyn Error + Send + Sync>>
+    where
+        T: Serialize,
+    {
+        post_json(&self.url, json).await
+    }
+}
+
+/* Posts JSON, waiting out rate limits and retrying transient errors */
+pub async fn post_json<T>(url: &str, json: &T) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    T: Serialize + ?Sized,
+{
+    let result = try_post_json(url, json).await;
+    if result.is_err() {
+        METRICS.webhook_failures.inc();
+    }
+    result
+}
+
+async fn try_post_json<T>(url: &str, json: &T) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    T: Serialize + ?Sized,
+{
+    let mut attempt = 1;
+    loop {
+        let (retry_in, reason) = match HTTP_CLIENT.post(url).json(json).send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => (
+                get_retry_after(response).await,
+                String::from("rate limited"),
+            ),
+            Ok(response) if response.status().is_server_error() => (
+                retry_backoff(attempt),
+                format!("responded with {}", response.status()),
+            ),
+            Ok(response) => {
+                return Err(format!("Webhook responded with {}", response.status()).into())
+            }
+            Err(e) if e.is_builder() => return Err(e.into()),
+            Err(e) => (retry_backoff(attempt), e.to_string()),
+        };
+
+        if attempt >= MAX_ATTEMPTS {
+            return Err(format!("Webhook failed after {} attempts: {}", attempt, reason).into());
+        }
+
+        tokio::time::sleep(retry_in).await;
+        attempt += 1;
+    }
+}
+
+/* How long a rate limited response says to wait (Discord sends retry_after in seconds in the body) */
+async fn get_retry_after(response: Response) -> Duration {
+    let header_secs = response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|retry_after| retry_after.to_str().ok())
+        .and_then(|retry_after| retry_after.parse::<f64>().ok());
+    let body_secs = response
+        .json::<Value>()
+        .await
+        .ok()
+        .and_then(|body| body.get("retry_after").and_then(Value::as_f64));
+
+    get_retry_duration(body_secs.or(header_secs))
+}
+
+/* Waits one second when the retry after is missing or not a number (e.g. NaN), and at most a minute */
+pub fn get_retry_duration(retry_after_secs: Option<f64>) -> Duration {
+    Duration::from_secs_f64(
+        retry_after_secs
+            .filter(|secs| secs.is_finite())
+            .unwrap_or(1.0)
+            .clamp(0.0, 60.0),
+    )
+}
+
+fn retry_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2_u64.pow(attempt - 1))
 }
@@ -0,0 +1,131 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use postgres_types::ToSql;
+use query_api::alerts::{check_webhook_url, AlertRule};
+
+fn rule() -> AlertRule {
+    AlertRule {
+        id: 1,
+        name: String::from("Cheap Hyperions"),
+        webhook_url: String::from("http://127.0.0.1/alert"),
+        webhook_type: String::from("HTTP"),
+        item_id: None,
+        internal_id: None,
+        tier: None,
+        bin: None,
+        max_price: None,
+        min_stars: None,
+        recombobulated: None,
+        enchants: Vec::new(),
+        created_t: 0,
+    }
+}
+
+#[test]
+fn rule_without_conditions_matches_nothing() {
+    let rule = rule();
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    assert!(!rule.has_conditions());
+    assert!(rule.get_conditions(&mut param_vec).is_empty());
+    assert!(param_vec.is_empty());
+}
+
+#[test]
+fn conditions_are_numbered_after_existing_params() {
+    let rule = AlertRule {
+        item_id: Some(String::from("HYPERION")),
+        max_price: Some(500000000),
+        enchants: vec![String::from("ULTIMATE_WISE;5")],
+        ..rule()
+    };
+    let (id, time_t) = (rule.id, 0_i64);
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = vec![&id, &time_t];
+
+    assert!(rule.has_conditions());
+    assert_eq!(
+        rule.get_conditions(&mut param_vec),
+        vec![
+            "item_id = $3",
+            "GREATEST(starting_bid, highest_bid) <= $4",
+            "enchants @> $5"
+        ]
+    );
+    assert_eq!(param_vec.len(), 5);
+}
+
+#[test]
+fn every_condition_is_added() {
+    let rule = AlertRule {
+        item_id: Some(String::from("PET")),
+        internal_id: Some(String::from("ENDER_DRAGON;4")),
+        tier: Some(String::from("LEGENDARY")),
+        bin: Some(true),
+        max_price: Some(1000),
+        min_stars: Some(5),
+        recombobulated: Some(false),
+        enchants: vec![String::from("SHARPNESS;6")],
+        ..rule()
+    };
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = Vec::new();
+
+    assert_eq!(
+        rule.get_conditions(&mut param_vec),
+        vec![
+            "item_id = $1",
+            "internal_id = $2",
+            "tier = $3",
+            "bin = $4",
+            "GREATEST(starting_bid, highest_bid) <= $5",
+            "stars >= $6",
+            "recombobulated = $7",
+            "enchants @> $8"
+        ]
+    );
+    assert_eq!(param_vec.len(), 8);
+}
+
+#[test]
+fn discord_webhooks_must_be_on_discord() {
+    assert!(check_webhook_url("DISCORD", "https://discord.com/api/webhooks/1/token").is_ok());
+    assert!(check_webhook_url("DISCORD", "https://canary.discord.com/api/webhooks/1/t").is_ok());
+    assert!(check_webhook_url("DISCORD", "http://discord.com/api/webhooks/1/token").is_err());
+    assert!(check_webhook_url("DISCORD", "https://example.com/api/webhooks/1/token").is_err());
+    assert!(check_webhook_url("DISCORD", "https://discord.com/api/users/@me").is_err());
+}
+
+#[test]
+fn http_webhooks_cannot_be_private() {
+    assert!(check_webhook_url("HTTP", "https://example.com/alert").is_ok());
+    assert!(check_webhook_url("HTTP", "https://8.8.8.8/alert").is_ok());
+    for url in [
+        "http://example.com/alert",
+        "https://localhost/alert",
+        "https://127.0.0.1:8000/alert",
+        "https://0x7f.1/alert",
+        "https://10.0.0.1/alert",
+        "https://169.254.169.254/latest/meta-data",
+        "https://100.64.0.1/alert",
+        "https://[::1]/alert",
+        "https://[::ffff:192.168.0.1]/alert",
+        "https://[fd00::1]/alert",
+        "not a url",
+    ] {
+        assert!(check_webhook_url("HTTP", url).is_err(), "{}", url);
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::api_keys::{ApiKey, Scope};
+
+fn api_key(scopes: &[&str], expires_at: Option<i64>, disabled: bool) -> ApiKey {
+    ApiKey {
+        id: 1,
+        label: String::from("partner"),
+        key_prefix: String::from("065a501d"),
+        scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        created_at: 0,
+        expires_at,
+        disabled,
+    }
+}
+
+#[test]
+fn parses_scopes() {
+    for scope in Scope::ALL {
+        assert_eq!(scope.as_str().parse::<Scope>(), Ok(scope));
+    }
+    assert!("QUERY".parse::<Scope>().is_err());
+    assert!("everything".parse::<Scope>().is_err());
+}
+
+#[test]
+fn keys_only_allow_their_scopes() {
+    let key = api_key(&["query", "averages"], None, false);
+    assert!(key.allows(Scope::Query, 1000));
+    assert!(key.allows(Scope::Averages, 1000));
+    assert!(!key.allows(Scope::RawSql, 1000));
+    assert!(!key.allows(Scope::Admin, 1000));
+
+    let admin = api_key(&["admin"], None, false);
+    assert!(Scope::ALL.iter().all(|scope| admin.allows(*scope, 1000)));
+}
+
+#[test]
+fn expired_and_disabled_keys_are_not_allowed() {
+    assert!(api_key(&["query"], Some(2000), false).allows(Scope::Query, 1000));
+    assert!(!api_key(&["query"], Some(2000), false).allows(Scope::Query, 2000));
+    assert!(!api_key(&["query"], None, true).allows(Scope::Query, 1000));
+    assert!(!api_key(&["admin"], None, true).allows(Scope::Query, 1000));
+}
+
+#[test]
+fn only_admin_scopes_need_the_admin_key() {
+    assert!(!Scope::Query.is_admin_only());
+    assert!(!Scope::Stream.is_admin_only());
+    assert!(Scope::RawSql.is_admin_only());
+    assert!(Scope::DebugLogs.is_admin_only());
+    assert!(Scope::Alerts.is_admin_only());
+    assert!(Scope::Metrics.is_admin_only());
+    assert!(Scope::Admin.is_admin_only());
+}
@@ -0,0 +1,112 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::structs::{AverageDatabaseItem, AverageHistoryItem, AvgAh};
+
+fn item(prices: &[(f32, f32)]) -> AverageDatabaseItem {
+    AverageDatabaseItem {
+        item_id: String::from("HYPERION"),
+        prices: prices
+            .iter()
+            .map(|(price, sales)| AvgAh {
+                price: *price,
+                sales: *sales,
+            })
+            .collect(),
+    }
+}
+
+fn prices(prices: &[f32]) -> AverageDatabaseItem {
+    item(&prices.iter().map(|price| (*price, 1.0)).collect::<Vec<_>>())
+}
+
+#[test]
+fn percentiles_interpolate_between_ranks() {
+    let item = prices(&[40.0, 10.0, 30.0, 20.0, 50.0]);
+    assert_eq!(item.get_percentile(0.0), 10.0);
+    assert_eq!(item.get_percentile(25.0), 20.0);
+    assert_eq!(item.get_percentile(50.0), 30.0);
+    assert_eq!(item.get_percentile(90.0), 46.0);
+    assert_eq!(item.get_percentile(100.0), 50.0);
+
+    assert_eq!(prices(&[7.0]).get_percentile(10.0), 7.0);
+}
+
+#[test]
+fn trimmed_mean_drops_both_ends() {
+    let item = prices(&[1.0, 10.0, 11.0, 12.0, 1000.0]);
+    assert_eq!(item.get_trimmed_mean(0.2), 11.0);
+
+    // Trimming everything falls back to the median
+    assert_eq!(prices(&[1.0, 3.0]).get_trimmed_mean(0.49), 2.0);
+}
+
+#[test]
+fn iqr_mean_rejects_outliers() {
+    let item = prices(&[10.0, 11.0, 12.0, 13.0, 14.0, 1000.0]);
+    assert_eq!(item.get_iqr_mean(1.5), 12.0);
+}
+
+#[test]
+fn mad_mean_rejects_outliers() {
+    let item = prices(&[10.0, 11.0, 12.0, 13.0, 14.0, 1000.0, 0.5]);
+    assert_eq!(item.get_mad_mean(3.0), 12.0);
+
+    // No spread keeps only prices equal to the median
+    assert_eq!(prices(&[5.0, 5.0, 5.0, 90.0]).get_mad_mean(3.0), 5.0);
+}
+
+#[test]
+fn weighted_mean_uses_sales() {
+    assert_eq!(
+        item(&[(10.0, 3.0), (20.0, 1.0)]).get_weighted_average(),
+        12.5
+    );
+
+    // Without sales it is the plain mean
+    assert_eq!(
+        item(&[(10.0, 0.0), (20.0, 0.0)]).get_weighted_average(),
+        15.0
+    );
+}
+
+#[test]
+fn history_groups_minutes_into_steps() {
+    let minute = |time: i32, price: f32, sales: f32| (time, AvgAh { price, sales });
+    let history = AverageHistoryItem::from_prices(
+        vec![
+            minute(7260, 40.0, 1.0),
+            minute(3600, 10.0, 3.0),
+            minute(3660, 20.0, 1.0),
+            minute(7200, 30.0, 1.0),
+        ],
+        3600,
+    );
+
+    assert_eq!(history.len(), 2);
+
+    assert_eq!(history[0].time, 3600);
+    assert_eq!(history[0].mean, 12.5);
+    assert_eq!(history[0].min, 10.0);
+    assert_eq!(history[0].max, 20.0);
+    assert_eq!(history[0].sales, 4.0);
+
+    assert_eq!(history[1].time, 7200);
+    assert_eq!(history[1].mean, 35.0);
+    assert_eq!(history[1].sales, 2.0);
+}
@@ -0,0 +1,63 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::cursor::QueryCursor;
+
+fn cursor(sort_by: &str, descending: bool, value: Option<i64>) -> QueryCursor {
+    QueryCursor {
+        sort_by: sort_by.to_string(),
+        descending,
+        value,
+        uuid: String::from("0000000000000000000000000117c877"),
+    }
+}
+
+#[test]
+fn decodes_encoded_cursors() {
+    let encoded = cursor("starting_bid", true, Some(43000000)).encode();
+    assert!(encoded
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    assert_eq!(
+        QueryCursor::decode(&encoded, "starting_bid", true),
+        Ok(cursor("starting_bid", true, Some(43000000)))
+    );
+
+    let encoded = cursor("uuid", false, None).encode();
+    assert_eq!(
+        QueryCursor::decode(&encoded, "uuid", false),
+        Ok(cursor("uuid", false, None))
+    );
+}
+
+#[test]
+fn rejects_cursors_from_another_sort() {
+    let encoded = cursor("starting_bid", true, Some(43000000)).encode();
+    assert!(QueryCursor::decode(&encoded, "starting_bid", false).is_err());
+    assert!(QueryCursor::decode(&encoded, "highest_bid", true).is_err());
+    assert!(QueryCursor::decode(&encoded, "uuid", false).is_err());
+}
+
+#[test]
+fn rejects_invalid_cursors() {
+    assert!(QueryCursor::decode("garbage", "uuid", false).is_err());
+    assert!(QueryCursor::decode("e30", "uuid", false).is_err());
+    // A cursor sorted by a column must have a value to continue from
+    let encoded = cursor("end_t", false, None).encode();
+    assert!(QueryCursor::decode(&encoded, "end_t", false).is_err());
+}
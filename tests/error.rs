@@ -0,0 +1,73 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::{
+    error::{QueryApiError, RawQueryError, SkippedAuctions},
+    utils::parse_nbt,
+};
+
+#[test]
+fn summary_is_empty_when_nothing_was_skipped() {
+    let skipped_auctions = SkippedAuctions::default();
+    assert_eq!(skipped_auctions.total(), 0);
+    assert_eq!(skipped_auctions.summary(), None);
+}
+
+#[test]
+fn summary_groups_skipped_auctions_by_reason() {
+    let skipped_auctions = SkippedAuctions::default();
+    skipped_auctions.record("a", &QueryApiError::MissingItem);
+    skipped_auctions.record("b", &QueryApiError::InvalidItemBytes);
+    skipped_auctions.record("c", &QueryApiError::InvalidItemBytes);
+
+    assert_eq!(skipped_auctions.total(), 3);
+    assert_eq!(
+        skipped_auctions.summary().as_deref(),
+        Some("Skipped 3 auctions (2 invalid item bytes, 1 missing item)")
+    );
+}
+
+#[test]
+fn malformed_item_bytes_are_errors() {
+    assert!(matches!(
+        parse_nbt("not base64"),
+        Err(QueryApiError::InvalidItemBytes)
+    ));
+    // Valid base64, but not gzipped NBT
+    assert!(matches!(
+        parse_nbt("aGVsbG8="),
+        Err(QueryApiError::InvalidItemBytes)
+    ));
+}
+
+#[test]
+fn raw_query_errors_are_structured() {
+    let error = RawQueryError::RowLimit(10000).to_json();
+    assert_eq!(error["success"], false);
+    assert_eq!(error["error"]["kind"], "row_limit");
+    assert!(error["error"]["sqlstate"].is_null());
+    assert!(error["reason"].as_str().unwrap().contains("10000"));
+
+    assert_eq!(RawQueryError::Busy.to_json()["error"]["kind"], "busy");
+}
+
+#[test]
+fn only_query_mistakes_are_client_errors() {
+    assert!(RawQueryError::RowLimit(1).is_client_error());
+    assert!(!RawQueryError::Busy.is_client_error());
+}
@@ -0,0 +1,296 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::{
+    error::QueryApiError,
+    item_key::{get_pet_level_bucket, is_pet_level_id, ItemKey},
+    structs::PartialExtraAttr,
+};
+use serde_json::{json, Value};
+
+fn item_key(extra_attrs: Value, display_name: &str) -> Result<ItemKey, QueryApiError> {
+    let extra_attrs: PartialExtraAttr = serde_json::from_value(extra_attrs).unwrap();
+    ItemKey::from_item(&extra_attrs, display_name)
+}
+
+fn internal_id(extra_attrs: Value, display_name: &str) -> String {
+    item_key(extra_attrs, display_name).unwrap().internal_id
+}
+
+#[test]
+fn plain_item_uses_id() {
+    let key = item_key(json!({"id": "HYPERION"}), "§dHeroic Hyperion ✪✪✪✪✪").unwrap();
+    assert_eq!(
+        key,
+        ItemKey {
+            internal_id: String::from("HYPERION"),
+            base_id: None,
+            units: 1
+        }
+    );
+}
+
+#[test]
+fn pet_uses_name_and_tier() {
+    let pet =
+        json!({"id": "PET", "petInfo": r#"{"tier":"LEGENDARY","heldItem":"PET_ITEM_TIER_BOOST"}"#});
+    assert_eq!(
+        internal_id(pet, "§7[Lvl 100] §6Ender Dragon"),
+        "ENDER_DRAGON;4"
+    );
+
+    let pet = json!({"id": "PET", "petInfo": r#"{"tier":"COMMON"}"#});
+    assert_eq!(
+        internal_id(pet, "[Lvl 1] Golden Dragon ✦"),
+        "GOLDEN_DRAGON;0"
+    );
+
+    let pet = json!({"id": "PET", "petInfo": r#"{"tier":"DIVINE"}"#});
+    assert_eq!(internal_id(pet, "[Lvl 1] Bee"), "BEE;-1");
+}
+
+fn pet_level_id(extra_attrs: Value, display_name: &str) -> Option<String> {
+    let extra_attrs: PartialExtraAttr = serde_json::from_value(extra_attrs).unwrap();
+    ItemKey::from_item(&extra_attrs, display_name)
+        .unwrap()
+        .get_pet_level_id(&extra_attrs, display_name)
+}
+
+#[test]
+fn pet_level_id_uses_level_bucket_and_held_item() {
+    let pet =
+        json!({"id": "PET", "petInfo": r#"{"tier":"EPIC","heldItem":"PET_ITEM_TIER_BOOST"}"#});
+    assert_eq!(
+        pet_level_id(pet, "§7[Lvl 96] §5Ender Dragon").as_deref(),
+        Some("ENDER_DRAGON;3+LVL_90+PET_ITEM_TIER_BOOST")
+    );
+
+    let pet = json!({"id": "PET", "petInfo": r#"{"tier":"LEGENDARY"}"#});
+    assert_eq!(
+        pet_level_id(pet, "[Lvl 200] Golden Dragon ✦").as_deref(),
+        Some("GOLDEN_DRAGON;4+LVL_200")
+    );
+
+    let pet = json!({"id": "PET", "petInfo": r#"{"tier":"LEGENDARY"}"#});
+    assert_eq!(pet_level_id(pet, "[Lvl ?] Bee"), None);
+
+    assert_eq!(pet_level_id(json!({"id": "HYPERION"}), "Hyperion"), None);
+}
+
+#[test]
+fn pet_level_buckets() {
+    assert_eq!(get_pet_level_bucket(1), 1);
+    assert_eq!(get_pet_level_bucket(49), 1);
+    assert_eq!(get_pet_level_bucket(50), 50);
+    assert_eq!(get_pet_level_bucket(99), 90);
+    assert_eq!(get_pet_level_bucket(100), 100);
+    assert_eq!(get_pet_level_bucket(199), 150);
+    assert_eq!(get_pet_level_bucket(200), 200);
+}
+
+#[test]
+fn pet_errors() {
+    assert!(matches!(
+        item_key(json!({"id": "PET"}), "[Lvl 1] Bee"),
+        Err(QueryApiError::MissingPetInfo)
+    ));
+    assert!(matches!(
+        item_key(json!({"id": "PET", "petInfo": "{"}), "[Lvl 1] Bee"),
+        Err(QueryApiError::InvalidPetInfo(_))
+    ));
+    assert!(matches!(
+        item_key(json!({"id": "PET", "petInfo": r#"{"tier":"RARE"}"#}), "Bee"),
+        Err(QueryApiError::InvalidPetName(_))
+    ));
+}
+
+#[test]
+fn attribute_shard_uses_attribute_and_level() {
+    let key = item_key(
+        json!({"id": "ATTRIBUTE_SHARD", "attributes": {"mana_pool": 3}}),
+        "Attribute Shard",
+    )
+    .unwrap();
+    assert_eq!(key.internal_id, "ATTRIBUTE_SHARD_MANA_POOL");
+    assert_eq!(key.base_id, None);
+    assert_eq!(key.units, 4);
+
+    let key = item_key(
+        json!({"id": "ATTRIBUTE_SHARD", "attributes": {"mana_pool": 1}}),
+        "Attribute Shard",
+    )
+    .unwrap();
+    assert_eq!(key.units, 1);
+}
+
+#[test]
+fn attribute_shard_with_multiple_attributes_is_unchanged() {
+    let key = item_key(
+        json!({"id": "ATTRIBUTE_SHARD", "attributes": {"mana_pool": 1, "veteran": 2}}),
+        "Attribute Shard",
+    )
+    .unwrap();
+    assert_eq!(key.internal_id, "ATTRIBUTE_SHARD");
+    assert_eq!(key.units, 1);
+}
+
+#[test]
+fn attributes_are_appended_in_order() {
+    let key = item_key(
+        json!({"id": "TERROR_BOOTS", "attributes": {"veteran": 1, "mana_pool": 2}}),
+        "Terror Boots",
+    )
+    .unwrap();
+    assert_eq!(
+        key.internal_id,
+        "TERROR_BOOTS+ATTRIBUTE_SHARD_MANA_POOL+ATTRIBUTE_SHARD_VETERAN"
+    );
+    assert_eq!(key.base_id.as_deref(), Some("TERROR_BOOTS"));
+    assert_eq!(key.units, 1);
+
+    let key = item_key(
+        json!({"id": "TERROR_BOOTS", "attributes": {}}),
+        "Terror Boots",
+    )
+    .unwrap();
+    assert_eq!(key.internal_id, "TERROR_BOOTS");
+    assert_eq!(key.base_id, None);
+}
+
+#[test]
+fn party_hats_use_color_and_emoji() {
+    assert_eq!(
+        internal_id(
+            json!({"id": "PARTY_HAT_CRAB", "party_hat_color": "red"}),
+            "Crab Hat"
+        ),
+        "PARTY_HAT_CRAB_RED"
+    );
+    assert_eq!(
+        internal_id(
+            json!({"id": "PARTY_HAT_CRAB_ANIMATED", "party_hat_color": "lime"}),
+            "Crab Hat"
+        ),
+        "PARTY_HAT_CRAB_LIME_ANIMATED"
+    );
+    assert_eq!(
+        internal_id(
+            json!({"id": "PARTY_HAT_SLOTH", "party_hat_emoji": "cool"}),
+            "Sloth Hat"
+        ),
+        "PARTY_HAT_SLOTH_COOL"
+    );
+    assert_eq!(
+        internal_id(json!({"id": "PARTY_HAT_CRAB"}), "Crab Hat"),
+        "PARTY_HAT_CRAB"
+    );
+}
+
+#[test]
+fn new_year_cake_uses_year() {
+    assert_eq!(
+        internal_id(
+            json!({"id": "NEW_YEAR_CAKE", "new_years_cake": 42}),
+            "New Year Cake"
+        ),
+        "NEW_YEAR_CAKE_42"
+    );
+}
+
+#[test]
+fn midas_uses_best_bid() {
+    assert_eq!(
+        internal_id(
+            json!({"id": "MIDAS_SWORD", "winning_bid": 60000000}),
+            "Midas' Sword"
+        ),
+        "MIDAS_SWORD_50000000"
+    );
+    assert_eq!(
+        internal_id(
+            json!({"id": "MIDAS_SWORD", "winning_bid": 50000000}),
+            "Midas' Sword"
+        ),
+        "MIDAS_SWORD"
+    );
+    assert_eq!(
+        internal_id(
+            json!({"id": "MIDAS_STAFF", "winning_bid": 100000001}),
+            "Midas Staff"
+        ),
+        "MIDAS_STAFF_100000000"
+    );
+    assert_eq!(
+        internal_id(
+            json!({"id": "MIDAS_STAFF", "winning_bid": 60000000}),
+            "Midas Staff"
+        ),
+        "MIDAS_STAFF"
+    );
+}
+
+#[test]
+fn rune_uses_type_and_level() {
+    assert_eq!(
+        internal_id(
+            json!({"id": "RUNE", "runes": {"music": 3}}),
+            "Music Rune III"
+        ),
+        "MUSIC_RUNE;3"
+    );
+    assert_eq!(
+        internal_id(
+            json!({"id": "RUNE", "runes": {"music": 3, "blood_2": 1}}),
+            "Rune"
+        ),
+        "RUNE"
+    );
+}
+
+#[test]
+fn shiny_is_appended_last() {
+    assert_eq!(
+        internal_id(json!({"id": "HYPERION", "is_shiny": 1}), "Shiny Hyperion"),
+        "HYPERION_SHINY"
+    );
+    assert_eq!(
+        internal_id(json!({"id": "HYPERION", "is_shiny": 0}), "Hyperion"),
+        "HYPERION"
+    );
+
+    let key = item_key(
+        json!({"id": "TERROR_BOOTS", "attributes": {"veteran": 1}, "is_shiny": 1}),
+        "Shiny Terror Boots",
+    )
+    .unwrap();
+    assert_eq!(
+        key.internal_id,
+        "TERROR_BOOTS+ATTRIBUTE_SHARD_VETERAN_SHINY"
+    );
+    assert_eq!(key.base_id.as_deref(), Some("TERROR_BOOTS"));
+}
+
+#[test]
+fn pet_level_ids_are_recognized() {
+    assert!(is_pet_level_id(
+        "ENDER_DRAGON;4+LVL_100+PET_ITEM_TIER_BOOST"
+    ));
+    assert!(is_pet_level_id("BEE;2+LVL_1"));
+    assert!(!is_pet_level_id("ENDER_DRAGON;4"));
+    assert!(!is_pet_level_id("HYPERION+ATTRIBUTE_SHARD_MANA_POOL;1"));
+}
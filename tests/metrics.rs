@@ -0,0 +1,83 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::{
+    metrics::{observe_fetch, observe_request, Metrics},
+    statics::METRICS,
+};
+use std::time::{Duration, Instant};
+
+#[test]
+fn encodes_metrics() {
+    let metrics = Metrics::new();
+    metrics.auctions_inserted.inc_by(5);
+    metrics
+        .update_seconds
+        .with_label_values(&["fetch"])
+        .observe(1.5);
+
+    let encoded = metrics.encode();
+    assert!(encoded.contains("# TYPE query_api_auctions_inserted_total counter"));
+    assert!(encoded.contains("query_api_auctions_inserted_total 5"));
+    assert!(encoded.contains("query_api_update_seconds_bucket{phase=\"fetch\",le=\"2\"} 1"));
+    assert!(encoded.contains("query_api_update_seconds_count{phase=\"fetch\"} 1"));
+    assert!(encoded.contains("query_api_webhook_failures_total 0"));
+}
+
+#[test]
+fn counts_fetched_pages() {
+    observe_fetch("auctions", Instant::now(), true);
+    observe_fetch("auctions", Instant::now(), true);
+    observe_fetch("auctions_ended", Instant::now(), false);
+
+    assert_eq!(
+        METRICS.pages_fetched.with_label_values(&["auctions"]).get(),
+        2
+    );
+    assert_eq!(
+        METRICS
+            .upstream_failures
+            .with_label_values(&["auctions_ended"])
+            .get(),
+        1
+    );
+    assert_eq!(
+        METRICS
+            .page_fetch_seconds
+            .with_label_values(&["auctions"])
+            .get_sample_count(),
+        2
+    );
+}
+
+#[test]
+fn labels_requests_by_route() {
+    observe_request("other", 404, Duration::from_millis(5));
+    observe_request("/lowestbin", 200, Duration::from_millis(30));
+
+    assert_eq!(
+        METRICS
+            .http_request_seconds
+            .with_label_values(&["other", "404"])
+            .get_sample_count(),
+        1
+    );
+    assert!(METRICS
+        .encode()
+        .contains("query_api_http_request_seconds_count{route=\"/lowestbin\",status=\"200\"} 1"));
+}
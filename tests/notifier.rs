@@ -0,0 +1,125 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::notifier::{coalesce, Notification, NotifierConfig, Severity};
+use std::{env, fs};
+
+fn notification(severity: Severity, description: &str) -> Notification {
+    Notification {
+        severity,
+        title: String::from("Information"),
+        description: description.to_string(),
+        mention: false,
+    }
+}
+
+#[test]
+fn parses_every_notifier_type() {
+    let notifiers: Vec<NotifierConfig> = serde_json::from_str(
+        r#"[
+            {"type": "discord", "url": "https://discord.com/api/webhooks/1/a", "mention": "<@1234>"},
+            {"type": "slack", "url": "https://hooks.slack.com/services/a", "severity": "ERROR"},
+            {"type": "http", "url": "http://127.0.0.1:9000/logs"},
+            {"type": "file", "path": "notifications.log"},
+            {"type": "stdout", "severity": "ERROR"}
+        ]"#,
+    )
+    .unwrap();
+
+    let severities = notifiers
+        .iter()
+        .map(|notifier| notifier.build().min_severity())
+        .collect::<Vec<Severity>>();
+    assert_eq!(
+        severities,
+        vec![
+            Severity::Info,
+            Severity::Error,
+            Severity::Info,
+            Severity::Info,
+            Severity::Error
+        ]
+    );
+}
+
+#[test]
+fn unknown_notifier_type_is_an_error() {
+    assert!(serde_json::from_str::<Vec<NotifierConfig>>(r#"[{"type": "email"}]"#).is_err());
+    assert!(serde_json::from_str::<Vec<NotifierConfig>>(
+        r#"[{"type": "stdout", "severity": "DEBUG"}]"#
+    )
+    .is_err());
+}
+
+#[test]
+fn errors_are_more_severe_than_info() {
+    assert!(Severity::Error > Severity::Info);
+}
+
+#[tokio::test]
+async fn file_notifier_appends_lines() {
+    let path = env::temp_dir().join(format!("query_api_notifier_{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let notifier = NotifierConfig::File {
+        path: path.to_string_lossy().to_string(),
+        severity: Severity::Info,
+    }
+    .build();
+    notifier
+        .notify(&notification(
+            Severity::Info,
+            "Inserted 5 auctions\nSkipped 1 auction",
+        ))
+        .await
+        .unwrap();
+    notifier
+        .notify(&notification(Severity::Error, "Failed"))
+        .await
+        .unwrap();
+
+    let lines = fs::read_to_string(&path).unwrap();
+    let lines = lines.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("Info Information: Inserted 5 auctions | Skipped 1 auction"));
+    assert!(lines[1].ends_with("Error Information: Failed"));
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn coalesces_notifications_with_the_same_title() {
+    let mut mentioned = notification(Severity::Info, "Second");
+    mentioned.mention = true;
+    let mut other_title = notification(Severity::Info, "Other");
+    other_title.title = String::from("Other");
+
+    let coalesced = coalesce(vec![
+        notification(Severity::Info, "First"),
+        notification(Severity::Error, "Failed"),
+        other_title,
+        mentioned,
+    ]);
+
+    assert_eq!(coalesced.len(), 3);
+    assert_eq!(coalesced[0].description, "First\nSecond");
+    assert!(coalesced[0].mention);
+    assert_eq!(coalesced[1].severity, Severity::Error);
+    assert_eq!(coalesced[1].description, "Failed");
+    assert_eq!(coalesced[2].title, "Other");
+}
@@ -0,0 +1,222 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use postgres_types::ToSql;
+use query_api::query_filter::{
+    get_select_columns, range_filter, Condition, FilterValue, QueryRequest, PRICE_PER_UNIT_SQL,
+};
+
+fn resolve(json: &str) -> Result<Condition, String> {
+    serde_json::from_str::<QueryRequest>(json)
+        .map_err(|e| e.to_string())?
+        .filter
+        .unwrap()
+        .resolve()
+}
+
+#[test]
+fn resolves_nested_groups() {
+    let condition = resolve(
+        r#"{"filter": {"and": [
+            {"eq": {"field": "bin", "value": true}},
+            {"range": {"field": "stars", "min": 4, "max": 5}},
+            {"or": [
+                {"in": {"field": "tier", "values": ["LEGENDARY", "MYTHIC"]}},
+                {"not": {"contains": {"field": "enchants", "values": ["GROWTH;6"]}}}
+            ]}
+        ]}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        condition,
+        Condition::And(vec![
+            Condition::Eq {
+                column: "bin",
+                value: FilterValue::Bool(true)
+            },
+            Condition::Range {
+                column: "stars",
+                min: Some(FilterValue::SmallInt(4)),
+                max: Some(FilterValue::SmallInt(5))
+            },
+            Condition::Or(vec![
+                Condition::In {
+                    column: "tier",
+                    values: vec![
+                        FilterValue::Text(String::from("LEGENDARY")),
+                        FilterValue::Text(String::from("MYTHIC"))
+                    ]
+                },
+                Condition::Not(Box::new(Condition::Contains {
+                    column: "enchants",
+                    values: vec![String::from("GROWTH;6")]
+                }))
+            ])
+        ])
+    );
+}
+
+#[test]
+fn request_defaults() {
+    let request = serde_json::from_str::<QueryRequest>("{}").unwrap();
+    assert!(request.filter.is_none());
+    assert!(request.sort_by.is_none());
+    assert_eq!(request.limit, 1);
+}
+
+#[test]
+fn rejects_unknown_fields_and_operators() {
+    assert!(
+        resolve(r#"{"filter": {"eq": {"field": "1=1; --", "value": 1}}}"#)
+            .unwrap_err()
+            .contains("Unknown field")
+    );
+    assert!(resolve(r#"{"filter": {"like": {"field": "item_name", "value": "%"}}}"#).is_err());
+    assert!(resolve(r#"{"filter": {}, "limit": 5}"#).is_err());
+    assert!(serde_json::from_str::<QueryRequest>(r#"{"filters": {}}"#).is_err());
+}
+
+#[test]
+fn rejects_values_of_the_wrong_type() {
+    assert!(resolve(r#"{"filter": {"eq": {"field": "stars", "value": "5"}}}"#).is_err());
+    assert!(resolve(r#"{"filter": {"eq": {"field": "stars", "value": 40000}}}"#).is_err());
+    assert!(resolve(r#"{"filter": {"in": {"field": "bin", "values": [true, 1]}}}"#).is_err());
+    assert_eq!(
+        resolve(r#"{"filter": {"eq": {"field": "starting_bid", "value": 5000000000}}}"#),
+        Ok(Condition::Eq {
+            column: "starting_bid",
+            value: FilterValue::BigInt(5000000000)
+        })
+    );
+}
+
+#[test]
+fn rejects_operators_that_do_not_fit_the_column() {
+    assert!(resolve(r#"{"filter": {"range": {"field": "tier", "min": "A"}}}"#).is_err());
+    assert!(resolve(r#"{"filter": {"range": {"field": "stars"}}}"#).is_err());
+    assert!(resolve(r#"{"filter": {"contains": {"field": "tier", "values": ["A"]}}}"#).is_err());
+    assert!(resolve(r#"{"filter": {"eq": {"field": "enchants", "value": "GROWTH;6"}}}"#).is_err());
+}
+
+fn fields(fields: &[&str]) -> Vec<String> {
+    fields.iter().map(|field| field.to_string()).collect()
+}
+
+#[test]
+fn selects_every_column_by_default() {
+    assert_eq!(get_select_columns(&[], false, &[]), Ok(None));
+    assert_eq!(get_select_columns(&[], false, &["uuid"]), Ok(None));
+}
+
+#[test]
+fn selects_requested_fields() {
+    assert_eq!(
+        get_select_columns(
+            &fields(&["item_id", " starting_bid", "item_id", "bids"]),
+            false,
+            &[]
+        ),
+        Ok(Some(String::from("item_id, starting_bid, bids")))
+    );
+    assert_eq!(
+        get_select_columns(&fields(&["item_id"]), false, &["uuid", "starting_bid"]),
+        Ok(Some(String::from("item_id, uuid, starting_bid")))
+    );
+    assert!(get_select_columns(&fields(&["item_id", "1; DROP TABLE query"]), false, &[]).is_err());
+}
+
+#[test]
+fn compact_leaves_out_lore_and_bids() {
+    let columns = get_select_columns(&[], true, &[]).unwrap().unwrap();
+    let columns = columns.split(", ").collect::<Vec<&str>>();
+    assert!(columns.contains(&"item_name"));
+    assert!(columns.contains(&"gemstones"));
+    assert!(!columns.contains(&"lore"));
+    assert!(!columns.contains(&"bids"));
+
+    assert_eq!(
+        get_select_columns(&fields(&["lore", "tier"]), true, &[]),
+        Ok(Some(String::from("tier")))
+    );
+    assert!(get_select_columns(&fields(&["lore"]), true, &[]).is_err());
+}
+
+#[test]
+fn range_filters_continue_the_where_clause() {
+    let (min_price, max_price) = (Some(1.5), Some(10.0));
+    let (min_stars, max_stars): (Option<i16>, Option<i16>) = (None, Some(5));
+    let mut sql = String::from("SELECT * FROM query WHERE");
+    let mut sort_by_query_end_sql = String::new();
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = Vec::new();
+
+    let param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        PRICE_PER_UNIT_SQL,
+        &min_price,
+        &max_price,
+        1,
+        false,
+    );
+    let param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "stars",
+        &min_stars,
+        &max_stars,
+        param_count,
+        false,
+    );
+
+    assert_eq!(
+        sql,
+        "SELECT * FROM query WHERE \
+        GREATEST(starting_bid, highest_bid)::DOUBLE PRECISION / GREATEST(count, 1) >= $1 AND \
+        GREATEST(starting_bid, highest_bid)::DOUBLE PRECISION / GREATEST(count, 1) <= $2 AND \
+        stars <= $3"
+    );
+    assert!(sort_by_query_end_sql.is_empty());
+    assert_eq!(param_count, 4);
+    assert_eq!(param_vec.len(), 3);
+}
+
+#[test]
+fn range_filters_go_after_the_score_when_sorting_by_query() {
+    let (min_count, max_count): (Option<i16>, Option<i16>) = (Some(2), None);
+    let mut sql = String::from("SELECT * FROM query WHERE item_name ILIKE $1");
+    let mut sort_by_query_end_sql = String::new();
+    let mut param_vec: Vec<&(dyn ToSql + Sync)> = Vec::new();
+
+    let param_count = range_filter(
+        &mut sql,
+        &mut sort_by_query_end_sql,
+        &mut param_vec,
+        "count",
+        &min_count,
+        &max_count,
+        2,
+        true,
+    );
+
+    assert_eq!(sql, "SELECT * FROM query WHERE item_name ILIKE $1");
+    assert_eq!(sort_by_query_end_sql, " count >= $2");
+    assert_eq!(param_count, 3);
+}
@@ -0,0 +1,194 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::rate_limit::{check_rate_limits, Limit, RateLimits, Usage};
+
+const DAY_MILLIS: i64 = 86400000;
+
+fn limit(per_minute: Option<f64>, burst: Option<f64>, daily: Option<u64>) -> Limit {
+    Limit {
+        per_minute,
+        burst,
+        daily,
+    }
+}
+
+#[test]
+fn refills_tokens() {
+    let limit = limit(Some(60.0), Some(2.0), None);
+    let mut usage = Usage::new(&limit, 0);
+
+    assert!(usage.take(&limit, 0).allowed);
+    let status = usage.take(&limit, 0);
+    assert!(status.allowed);
+    assert_eq!(status.rate, Some((2, 0, 2)));
+
+    let status = usage.take(&limit, 500);
+    assert!(!status.allowed);
+    assert_eq!(status.retry_after, 1);
+
+    assert!(usage.take(&limit, 1000).allowed);
+    // The bucket never holds more than the burst
+    assert!(usage.take(&limit, 60000).allowed);
+    assert!(usage.take(&limit, 60000).allowed);
+    assert!(!usage.take(&limit, 60000).allowed);
+}
+
+#[test]
+fn resets_daily_quota() {
+    let limit = limit(None, None, Some(2));
+    let mut usage = Usage::new(&limit, 0);
+
+    assert!(usage.take(&limit, 0).allowed);
+    assert!(usage.take(&limit, 1000).allowed);
+    let status = usage.take(&limit, DAY_MILLIS - 10000);
+    assert!(!status.allowed);
+    assert_eq!(status.retry_after, 10);
+    assert_eq!(status.quota, Some((2, 0, 10)));
+    assert_eq!(status.rate, None);
+
+    let status = usage.take(&limit, DAY_MILLIS);
+    assert!(status.allowed);
+    assert_eq!(status.quota, Some((2, 1, 86400)));
+}
+
+#[test]
+fn refunds_request() {
+    let limit = limit(Some(1.0), None, Some(5));
+    let mut usage = Usage::new(&limit, 0);
+
+    assert!(usage.take(&limit, 0).allowed);
+    usage.refund(&limit);
+    let status = usage.take(&limit, 0);
+    assert!(status.allowed);
+    assert_eq!(status.quota, Some((5, 4, 86400)));
+}
+
+#[test]
+fn idle_once_refilled() {
+    let limit = limit(Some(60.0), Some(2.0), None);
+    let mut usage = Usage::new(&limit, 0);
+
+    usage.take(&limit, 0);
+    usage.take(&limit, 0);
+    assert!(!usage.is_idle(1999));
+    assert!(usage.is_idle(2000));
+}
+
+#[test]
+fn idle_once_quota_resets() {
+    let limit = limit(Some(60.0), None, Some(5));
+    let mut usage = Usage::new(&limit, 0);
+
+    usage.take(&limit, 1000);
+    assert!(!usage.is_idle(2000));
+    assert!(!usage.is_idle(DAY_MILLIS - 1));
+    assert!(usage.is_idle(DAY_MILLIS));
+}
+
+#[test]
+fn checks_ip_and_key_limits() {
+    let rate_limits: RateLimits = serde_json::from_str(
+        r#"{
+            "default": {"ip": {"per_minute": 100}},
+            "endpoints": {
+                "/rate_limit_test": {
+                    "ip": {"per_minute": 3},
+                    "key": {"per_minute": 60, "burst": 1}
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    assert!(
+        check_rate_limits(&rate_limits, "/rate_limit_test", "10.0.0.1", None, 0)
+            .unwrap()
+            .allowed
+    );
+    assert!(
+        check_rate_limits(&rate_limits, "/rate_limit_test", "10.0.0.1", Some("a"), 0)
+            .unwrap()
+            .allowed
+    );
+
+    // Denied by the key limit, which gives the IP token back
+    let status =
+        check_rate_limits(&rate_limits, "/rate_limit_test", "10.0.0.1", Some("a"), 0).unwrap();
+    assert!(!status.allowed);
+    assert!(
+        check_rate_limits(&rate_limits, "/rate_limit_test", "10.0.0.1", Some("b"), 0)
+            .unwrap()
+            .allowed
+    );
+
+    // The IP limit applies to every key
+    let status =
+        check_rate_limits(&rate_limits, "/rate_limit_test", "10.0.0.1", Some("c"), 0).unwrap();
+    assert!(!status.allowed);
+    assert_eq!(status.retry_after, 20);
+    assert!(
+        check_rate_limits(&rate_limits, "/rate_limit_test", "10.0.0.2", Some("c"), 0)
+            .unwrap()
+            .allowed
+    );
+}
+
+#[test]
+fn uses_default_limits() {
+    let rate_limits: RateLimits =
+        serde_json::from_str(r#"{"endpoints": {"/rate_limit_default": {"ip": {"daily": 1}}}}"#)
+            .unwrap();
+
+    assert!(check_rate_limits(&rate_limits, "/rate_limit_other", "10.0.0.1", None, 0).is_none());
+    assert!(
+        check_rate_limits(&rate_limits, "/rate_limit_default", "10.0.0.3", None, 0)
+            .unwrap()
+            .allowed
+    );
+    assert!(
+        !check_rate_limits(&rate_limits, "/rate_limit_default", "10.0.0.3", None, 0)
+            .unwrap()
+            .allowed
+    );
+}
+
+#[test]
+fn unknown_keys_share_the_ip_limit() {
+    let rate_limits: RateLimits = serde_json::from_str(
+        r#"{
+            "endpoints": {
+                "/rate_limit_unknown": {
+                    "ip": {"per_minute": 2},
+                    "key": {"per_minute": 100}
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    // Requests with random keys are checked as if they had no key
+    let allowed = (0..5)
+        .filter(|_| {
+            check_rate_limits(&rate_limits, "/rate_limit_unknown", "10.0.0.4", None, 0)
+                .unwrap()
+                .allowed
+        })
+        .count();
+    assert_eq!(allowed, 2);
+}
@@ -0,0 +1,53 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::{
+    rate_limit::{check_rate_limits, prune_rate_limits, RateLimits, MAX_TRACKED},
+    statics::RATE_LIMIT_USAGE,
+};
+
+// Fills the tracked clients, so it is kept apart from the other rate limit tests
+#[test]
+fn new_clients_are_limited_when_full() {
+    let rate_limits: RateLimits =
+        serde_json::from_str(r#"{"default": {"ip": {"per_minute": 60, "burst": 1}}}"#).unwrap();
+    for i in 0..MAX_TRACKED {
+        let ip = format!("10.{}.{}.{}", i >> 16, (i >> 8) & 255, i & 255);
+        check_rate_limits(&rate_limits, "/", &ip, None, 0);
+    }
+    assert_eq!(RATE_LIMIT_USAGE.len(), MAX_TRACKED);
+
+    // The least recently used client is forgotten to track the new one
+    assert!(
+        check_rate_limits(&rate_limits, "/", "192.168.0.1", None, 500)
+            .unwrap()
+            .allowed
+    );
+    assert!(
+        !check_rate_limits(&rate_limits, "/", "192.168.0.1", None, 500)
+            .unwrap()
+            .allowed
+    );
+    assert_eq!(RATE_LIMIT_USAGE.len(), MAX_TRACKED);
+
+    // Clients are forgotten once their bucket has refilled
+    prune_rate_limits(1000);
+    assert_eq!(RATE_LIMIT_USAGE.len(), 1);
+    prune_rate_limits(1500);
+    assert!(RATE_LIMIT_USAGE.is_empty());
+}
@@ -0,0 +1,129 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::{
+    config::{Config, SnapshotMode},
+    mock::{start_mock_server, MockConfig},
+    rate_limit::RateLimits,
+    snapshot::{begin_snapshot, get_replay_last_updated, get_snapshots, get_upstream},
+    statics::SNAPSHOT,
+    underbin::UnderbinRules,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashSet, env, fs, net::TcpListener, path::Path, sync::atomic::AtomicI64,
+    sync::Arc, time::Duration,
+};
+
+fn config(hypixel_api_url: String, snapshot_mode: SnapshotMode, snapshot_dir: &Path) -> Config {
+    Config {
+        enabled_features: HashSet::new(),
+        webhook_url: String::new(),
+        notifiers: Vec::new(),
+        notification_window_ms: 0,
+        usage_retention_days: 30,
+        raw_query_timeout_ms: 0,
+        raw_query_row_limit: 0,
+        raw_query_concurrency: 0,
+        stream_max_subscribers: 100,
+        base_url: String::from("127.0.0.1"),
+        port: 0,
+        full_url: String::from("127.0.0.1:0"),
+        postgres_url: String::new(),
+        hypixel_api_url,
+        snapshot_mode: Some(snapshot_mode),
+        snapshot_dir: snapshot_dir.to_string_lossy().to_string(),
+        underbin_rules: UnderbinRules::default(),
+        rate_limits: RateLimits::default(),
+        api_key: String::new(),
+        admin_api_key: String::new(),
+        debug: false,
+        disable_updating: false,
+        super_secret_config_option: false,
+    }
+}
+
+#[tokio::test]
+async fn recorded_runs_are_replayed() {
+    let dir = env::temp_dir().join(format!("query_api_snapshot_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let (data_dir, snapshot_dir) = (dir.join("mock_data"), dir.join("snapshots"));
+
+    let page =
+        json!({"success": true, "page": 0, "totalPages": 1, "lastUpdated": 1000, "auctions": []});
+    let ended = json!({"success": true, "lastUpdated": 1000, "auctions": []});
+    fs::create_dir_all(data_dir.join("auctions")).unwrap();
+    fs::write(data_dir.join("auctions/0.json"), page.to_string()).unwrap();
+    fs::write(data_dir.join("auctions_ended.json"), ended.to_string()).unwrap();
+
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(start_mock_server(Arc::new(MockConfig {
+        data_dir,
+        address,
+        age: AtomicI64::new(0),
+        rewrite_last_updated: false,
+    })));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Record a run from the mock upstream
+    let record = config(
+        format!("http://{}", address),
+        SnapshotMode::Record,
+        &snapshot_dir,
+    );
+    begin_snapshot(&record, 60000, 1000).await;
+    let recorded_page: Value =
+        get_upstream(&record, "/skyblock/auctions?page=0", "auctions/0.json")
+            .await
+            .unwrap();
+    let recorded_ended: Value =
+        get_upstream(&record, "/skyblock/auctions_ended", "auctions_ended.json")
+            .await
+            .unwrap();
+    assert_eq!(recorded_page, page);
+    assert_eq!(recorded_ended, ended);
+
+    // Replay it without the upstream
+    let replay = config(
+        String::from("http://127.0.0.1:1"),
+        SnapshotMode::Replay,
+        &snapshot_dir,
+    );
+    let snapshots = get_snapshots(&replay.snapshot_dir).unwrap();
+    assert_eq!(snapshots, vec![snapshot_dir.join("60000")]);
+    let _ = SNAPSHOT.lock().await.insert(snapshots[0].clone());
+
+    assert_eq!(get_replay_last_updated(&replay).await, Some(1000));
+    assert_eq!(
+        get_upstream::<Value>(&replay, "/skyblock/auctions?page=0", "auctions/0.json").await,
+        Some(page)
+    );
+    assert_eq!(
+        get_upstream::<Value>(&replay, "/skyblock/auctions_ended", "auctions_ended.json").await,
+        Some(ended)
+    );
+    assert_eq!(
+        get_upstream::<Value>(&replay, "/skyblock/auctions?page=1", "auctions/1.json").await,
+        None
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,99 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::stream::{subscribe, StreamEvent, StreamFilter};
+use serde_json::json;
+
+fn event(event: &'static str, tier: Option<&str>, bin: bool, price: i64) -> StreamEvent {
+    StreamEvent {
+        event,
+        item_id: String::from("PET"),
+        internal_id: String::from("ENDER_DRAGON;4"),
+        tier: tier.map(String::from),
+        bin,
+        price,
+        data: json!({"uuid": "1"}),
+    }
+}
+
+#[test]
+fn empty_filter_matches_everything() {
+    let filter = StreamFilter::default();
+    assert!(filter.matches(&event("auction", Some("LEGENDARY"), true, 1)));
+    assert!(filter.matches(&event("ended_auction", None, false, 1)));
+}
+
+#[test]
+fn filters_by_event_and_item_id() {
+    let filter = StreamFilter {
+        events: vec![String::from("underbin"), String::from("auction")],
+        ..Default::default()
+    };
+    assert!(filter.matches(&event("underbin", None, true, 1)));
+    assert!(!filter.matches(&event("ended_auction", None, true, 1)));
+
+    // Either the item id or the internal id
+    for item_id in ["PET", "ENDER_DRAGON;4"] {
+        let filter = StreamFilter {
+            item_ids: vec![String::from("HYPERION"), String::from(item_id)],
+            ..Default::default()
+        };
+        assert!(filter.matches(&event("auction", None, true, 1)));
+    }
+    let filter = StreamFilter {
+        item_ids: vec![String::from("ENDER_DRAGON;3")],
+        ..Default::default()
+    };
+    assert!(!filter.matches(&event("auction", None, true, 1)));
+}
+
+#[test]
+fn filters_by_tier_bin_and_price() {
+    let filter = StreamFilter {
+        tier: Some(String::from("LEGENDARY")),
+        bin: Some(true),
+        max_price: Some(100),
+        ..Default::default()
+    };
+    assert!(filter.matches(&event("auction", Some("LEGENDARY"), true, 100)));
+    assert!(!filter.matches(&event("auction", Some("EPIC"), true, 100)));
+    assert!(!filter.matches(&event("auction", Some("LEGENDARY"), false, 100)));
+    assert!(!filter.matches(&event("auction", Some("LEGENDARY"), true, 101)));
+
+    // Ended auctions have no tier, so they never match a tier filter
+    assert!(!filter.matches(&event("ended_auction", None, true, 100)));
+}
+
+#[test]
+fn events_are_formatted_as_sse() {
+    assert_eq!(
+        event("auction", None, true, 1).to_sse(),
+        "event: auction\ndata: {\"uuid\":\"1\"}\n\n"
+    );
+}
+
+#[test]
+fn subscribers_are_capped() {
+    let first = subscribe(2);
+    let second = subscribe(2);
+    assert!(first.is_some() && second.is_some());
+    assert!(subscribe(2).is_none());
+
+    drop(first);
+    assert!(subscribe(2).is_some());
+}
@@ -0,0 +1,168 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::{
+    structs::{AverageDatabaseItem, AvgAh},
+    underbin::{UnderbinCandidate, UnderbinRules, UnderbinScore},
+    utils::calculate_with_taxes,
+};
+
+fn candidate<'a>(id: &'a str, price: f32, profit: f32) -> UnderbinCandidate<'a> {
+    UnderbinCandidate {
+        id,
+        name: "Hyperion",
+        lore: "",
+        category: "weapon",
+        price,
+        profit,
+        confidence: 1.0,
+    }
+}
+
+fn parse_rules(json: &str) -> UnderbinRules {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn default_rules_match_previous_behavior() {
+    let rules = UnderbinRules::default();
+    assert!(rules.is_underbin(&candidate("HYPERION", 500000000.0, 1500000.0)));
+    assert!(!rules.is_underbin(&candidate("HYPERION", 500000000.0, 1000000.0)));
+
+    assert!(!rules.is_allowed("MINION_SKIN", "Sheep Minion Skin", ""));
+    assert!(!rules.is_allowed("CHAIR", "Chair", "§8Furniture"));
+    assert!(!rules.is_allowed("UNKNOWN", "null", ""));
+    assert!(rules.is_allowed("HYPERION", "Hyperion", ""));
+}
+
+#[test]
+fn missing_fields_use_defaults() {
+    let rules = parse_rules(r#"{"min_profit_percent": 10}"#);
+    assert_eq!(rules.min_profit, 1000000.0);
+    assert!(!rules.is_allowed("CHAIR", "Chair", "Furniture"));
+    assert!(rules.is_underbin(&candidate("HYPERION", 10000000.0, 1500000.0)));
+    assert!(!rules.is_underbin(&candidate("HYPERION", 20000000.0, 1500000.0)));
+}
+
+#[test]
+fn max_price() {
+    let rules = parse_rules(r#"{"max_price": 100000000}"#);
+    assert!(rules.is_underbin(&candidate("HYPERION", 100000000.0, 5000000.0)));
+    assert!(!rules.is_underbin(&candidate("HYPERION", 150000000.0, 5000000.0)));
+}
+
+#[test]
+fn include_and_exclude_lists() {
+    let rules = parse_rules(
+        r#"{"include_ids": ["HYPERION"], "include_regex": ["^ENDER_DRAGON;"], "exclude_regex": [";3$"]}"#,
+    );
+    assert!(rules.is_allowed("HYPERION", "Hyperion", ""));
+    assert!(rules.is_allowed("ENDER_DRAGON;4", "Ender Dragon", ""));
+    assert!(!rules.is_allowed("ENDER_DRAGON;3", "Ender Dragon", ""));
+    assert!(!rules.is_allowed("TERMINATOR", "Terminator", ""));
+
+    let rules = parse_rules(r#"{"exclude_ids": ["HYPERION"], "exclude_lore_regex": []}"#);
+    assert!(!rules.is_allowed("HYPERION", "Hyperion", ""));
+    assert!(rules.is_allowed("CHAIR", "Chair", "Furniture"));
+
+    let rules = parse_rules(r#"{"exclude_name_regex": ["^Sheep Minion"]}"#);
+    assert!(!rules.is_allowed("MINION_SKIN", "§aSheep Minion Skin", ""));
+}
+
+#[test]
+fn first_matching_override_is_used() {
+    let rules = parse_rules(
+        r#"{
+            "overrides": [
+                {"ids": ["HYPERION"], "min_profit": 20000000},
+                {"category": "WEAPON", "min_profit": 100, "max_price": 1000000}
+            ]
+        }"#,
+    );
+    assert!(!rules.is_underbin(&candidate("HYPERION", 500000000.0, 5000000.0)));
+    assert!(rules.is_underbin(&candidate("HYPERION", 500000000.0, 25000000.0)));
+
+    assert!(rules.is_underbin(&candidate("TERMINATOR", 1000000.0, 500.0)));
+    assert!(!rules.is_underbin(&candidate("TERMINATOR", 2000000.0, 500.0)));
+}
+
+#[test]
+fn invalid_regex_is_an_error() {
+    assert!(serde_json::from_str::<UnderbinRules>(r#"{"exclude_regex": ["("]}"#).is_err());
+}
+
+#[test]
+fn min_confidence() {
+    let rules = parse_rules(
+        r#"{"min_confidence": 0.5, "overrides": [{"ids": ["TERMINATOR"], "min_confidence": 0}]}"#,
+    );
+    let mut unsure = candidate("HYPERION", 500000000.0, 5000000.0);
+    unsure.confidence = 0.4;
+    assert!(!rules.is_underbin(&unsure));
+    unsure.confidence = 0.5;
+    assert!(rules.is_underbin(&unsure));
+
+    let mut unsure = candidate("TERMINATOR", 500000000.0, 5000000.0);
+    unsure.confidence = 0.0;
+    assert!(rules.is_underbin(&unsure));
+}
+
+fn recent_bins(prices: &[(f32, f32)]) -> AverageDatabaseItem {
+    AverageDatabaseItem {
+        item_id: String::from("HYPERION"),
+        prices: prices
+            .iter()
+            .map(|(price, sales)| AvgAh {
+                price: *price,
+                sales: *sales,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn score_without_sales_uses_lowest_bin() {
+    let rules = UnderbinRules::default();
+    let score = UnderbinScore::new(10000000.0, 20000000.0, None, &rules);
+    assert_eq!(score.expected_resale_price, 20000000.0);
+    assert_eq!(score.profit, calculate_with_taxes(20000000.0) - 10000000.0);
+    assert_eq!(score.liquidity, 0.0);
+    assert_eq!(score.confidence, 0.0);
+}
+
+#[test]
+fn score_uses_median_when_lowest_bin_is_mislisted() {
+    let rules = UnderbinRules::default();
+    // Sold for 10m about 10 times a day, but the previous lowest bin was 40m
+    let recent_bins = recent_bins(&[(9000000.0, 4.0), (10000000.0, 3.0), (11000000.0, 3.0)]);
+    let score = UnderbinScore::new(9500000.0, 40000000.0, Some(&recent_bins), &rules);
+    assert_eq!(score.expected_resale_price, 10000000.0);
+    assert_eq!(score.profit, calculate_with_taxes(10000000.0) - 9500000.0);
+    assert_eq!(score.liquidity, 10.0);
+    assert_eq!(score.confidence, 0.25 * 10.0 / 15.0);
+}
+
+#[test]
+fn score_is_confident_when_lowest_bin_and_median_agree() {
+    let rules = UnderbinRules::default();
+    let recent_bins = recent_bins(&[(20000000.0, 95.0)]);
+    let score = UnderbinScore::new(10000000.0, 20000000.0, Some(&recent_bins), &rules);
+    assert_eq!(score.expected_resale_price, 20000000.0);
+    assert_eq!(score.liquidity, 95.0);
+    assert_eq!(score.confidence, 0.95);
+}
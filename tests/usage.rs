@@ -0,0 +1,84 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::usage::{UsageReport, UsageStats, LATENCY_BUCKETS_MS};
+use std::collections::BTreeMap;
+
+#[test]
+fn records_latency_buckets() {
+    let mut stats = UsageStats::default();
+    stats.record(0.5, 100);
+    stats.record(1.0, 100);
+    stats.record(4.0, 0);
+    stats.record(60000.0, 50);
+
+    assert_eq!(stats.requests, 4);
+    assert_eq!(stats.response_bytes, 250);
+    assert_eq!(stats.max_latency_ms, 60000.0);
+    assert_eq!(stats.latency_buckets.len(), LATENCY_BUCKETS_MS.len() + 1);
+    assert_eq!(stats.latency_buckets[0], 2);
+    assert_eq!(stats.latency_buckets[3], 1);
+    assert_eq!(stats.latency_buckets[LATENCY_BUCKETS_MS.len()], 1);
+}
+
+#[test]
+fn estimates_percentiles() {
+    let mut stats = UsageStats::default();
+    assert_eq!(stats.percentile(50.0), 0.0);
+
+    for _ in 0..90 {
+        stats.record(8.0, 0);
+    }
+    for _ in 0..9 {
+        stats.record(120.0, 0);
+    }
+    stats.record(40000.0, 0);
+
+    assert_eq!(stats.percentile(50.0), 10.0);
+    assert_eq!(stats.percentile(90.0), 10.0);
+    assert_eq!(stats.percentile(99.0), 150.0);
+    // Slower than the last bound, so the max is used
+    assert_eq!(stats.percentile(100.0), 40000.0);
+
+    // Never more than the slowest request
+    let mut stats = UsageStats::default();
+    stats.record(3.5, 0);
+    assert_eq!(stats.percentile(50.0), 3.5);
+}
+
+#[test]
+fn merges_stats() {
+    let mut a = UsageStats::default();
+    a.record(2.0, 10);
+    let mut b = UsageStats::default();
+    b.record(6.0, 20);
+    b.record(6.0, 20);
+    a.merge(&b);
+
+    let report = UsageReport::new(
+        String::from("admin"),
+        String::from("/query"),
+        BTreeMap::from([(String::from("200"), 3)]),
+        &a,
+    );
+    assert_eq!(report.requests, 3);
+    assert_eq!(report.response_bytes, 50);
+    assert_eq!(report.latency_ms.average, 14.0 / 3.0);
+    assert_eq!(report.latency_ms.p50, 6.0);
+    assert_eq!(report.latency_ms.max, 6.0);
+}
@@ -0,0 +1,129 @@
+/*
+ * Rust Query API - A versatile API facade for the Hypixel Auction API
+ * Copyright (c) 2022 kr45732
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use query_api::webhook::{get_retry_duration, Message};
+use serde_json::Value;
+use std::time::Duration;
+
+fn split(message: &Message) -> Vec<Value> {
+    message
+        .split()
+        .iter()
+        .map(|part| serde_json::to_value(part).unwrap())
+        .collect()
+}
+
+fn description_len(embed: &Value) -> usize {
+    embed["description"].as_str().unwrap().chars().count()
+}
+
+#[test]
+fn short_messages_are_not_split() {
+    let mut message = Message::new();
+    message
+        .mention("<@1234>")
+        .embed(|e| e.title("Information").description("Inserted 5 auctions"));
+
+    let parts = split(&message);
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0]["content"], "<@1234>");
+    assert_eq!(parts[0]["embeds"][0]["description"], "Inserted 5 auctions");
+}
+
+#[test]
+fn long_descriptions_are_split_between_lines() {
+    let line = "a".repeat(99);
+    let description = vec![line.as_str(); 100].join("\n");
+    let mut message = Message::new();
+    message.embed(|e| e.title("Information").description(&description));
+
+    let parts = split(&message);
+    let embeds = parts
+        .iter()
+        .flat_map(|part| part["embeds"].as_array().unwrap().clone())
+        .collect::<Vec<Value>>();
+
+    assert!(embeds.len() > 2);
+    assert!(embeds.iter().all(|embed| description_len(embed) <= 4096));
+    assert!(embeds.iter().all(|embed| embed["title"] == "Information"));
+    assert!(embeds.iter().all(|embed| embed["description"]
+        .as_str()
+        .unwrap()
+        .split('\n')
+        .all(|l| l == line)));
+    assert_eq!(
+        embeds
+            .iter()
+            .map(|embed| embed["description"].as_str().unwrap())
+            .collect::<Vec<&str>>()
+            .join("\n"),
+        description
+    );
+}
+
+#[test]
+fn long_lines_are_split_at_the_limit() {
+    let description = "b".repeat(5000);
+    let mut message = Message::new();
+    message.embed(|e| e.description(&description));
+
+    let parts = split(&message);
+    let embeds = parts[0]["embeds"].as_array().unwrap();
+    assert_eq!(embeds.len(), 2);
+    assert_eq!(description_len(&embeds[0]), 4096);
+    assert_eq!(description_len(&embeds[1]), 904);
+}
+
+#[test]
+fn messages_stay_within_embed_limits() {
+    let mut message = Message::new();
+    message.content("<@1234>");
+    for _ in 0..12 {
+        message.embed(|e| e.title("Error").description(&"c".repeat(1000)));
+    }
+
+    let parts = split(&message);
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0]["content"], "<@1234>");
+    assert!(parts[1]["content"].is_null());
+    for part in &parts {
+        let embeds = part["embeds"].as_array().unwrap();
+        assert!(embeds.len() <= 10);
+        let characters = embeds
+            .iter()
+            .map(|embed| description_len(embed) + embed["title"].as_str().unwrap().len())
+            .sum::<usize>();
+        assert!(characters <= 6000);
+    }
+}
+
+#[test]
+fn retry_after_is_clamped_and_ignores_non_finite_values() {
+    assert_eq!(get_retry_duration(Some(2.5)), Duration::from_millis(2500));
+    assert_eq!(get_retry_duration(Some(120.0)), Duration::from_secs(60));
+    assert_eq!(get_retry_duration(Some(-5.0)), Duration::ZERO);
+    assert_eq!(get_retry_duration(None), Duration::from_secs(1));
+    assert_eq!(
+        get_retry_duration("NaN".parse().ok()),
+        Duration::from_secs(1)
+    );
+    assert_eq!(
+        get_retry_duration("inf".parse().ok()),
+        Duration::from_secs(1)
+    );
+}